@@ -0,0 +1,157 @@
+//! Ready-made textual dumps of the running processes, for diagnostics and bug reports.
+
+use crate::{process_tree, ProcessEntry, Snapshot};
+
+use std::{
+    fmt::Write as _,
+    io::{Result, Write},
+};
+
+/// A column header and how to format its cell for a process.
+type Column = (&'static str, fn(&ProcessEntry) -> String);
+
+/// Selects the columns and the layout of [`dump_processes`].
+/// The default options produce the table of [`dump_process_table`]: the process id, parent
+/// process id, thread count and name of every process, laid out as a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessDumpOptions {
+    /// Include the process id.
+    pub pid: bool,
+    /// Include the parent process id.
+    pub ppid: bool,
+    /// Include the thread count.
+    pub threads: bool,
+    /// Include the base priority.
+    pub priority: bool,
+    /// Include the session id, queried per process via [`ProcessEntry::session_id`]. Processes
+    /// whose session can't be queried show a `-`.
+    pub session: bool,
+    /// Include the executable name. It is always the last column.
+    pub name: bool,
+    /// Order the processes as by [`process_tree`] and indent their names by their depth, instead
+    /// of listing them in snapshot order.
+    pub tree: bool,
+}
+
+impl Default for ProcessDumpOptions {
+    fn default() -> Self {
+        ProcessDumpOptions {
+            pid: true,
+            ppid: true,
+            threads: true,
+            priority: false,
+            session: false,
+            name: true,
+            tree: true,
+        }
+    }
+}
+
+/// Takes a process snapshot and writes it to `w` as a table of the process id, parent process id,
+/// thread count and executable name of every process. The processes are ordered as by
+/// [`process_tree`], with the names indented by their depth to show the nesting.
+/// The numeric columns are right aligned and sized to fit their widest value, the name comes last
+/// so long names don't break the alignment.
+/// # Errors
+/// This function fails if it is unable to create the snapshot or if writing to `w` fails.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// tlhelp32::dump_process_table(&mut std::io::stdout().lock())?;
+/// ```
+pub fn dump_process_table(w: &mut impl Write) -> Result<()> {
+    dump_processes(w, ProcessDumpOptions::default())
+}
+
+/// Like [`dump_process_table`], but with the columns and layout picked by `opts`.
+/// # Errors
+/// This function fails if it is unable to create the snapshot or if writing to `w` fails.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let opts = tlhelp32::ProcessDumpOptions {
+///     session: true,
+///     tree: false,
+///     ..Default::default()
+/// };
+/// tlhelp32::dump_processes(&mut std::io::stdout().lock(), opts)?;
+/// ```
+pub fn dump_processes(w: &mut impl Write, opts: ProcessDumpOptions) -> Result<()> {
+    let processes = if opts.tree {
+        process_tree()?
+    } else {
+        Snapshot::new_process()?.map(|entry| (0, entry)).collect()
+    };
+
+    let mut columns: Vec<Column> = Vec::new();
+    if opts.pid {
+        columns.push(("PID", |entry| entry.process_id.to_string()));
+    }
+    if opts.ppid {
+        columns.push(("PPID", |entry| entry.parent_process_id.to_string()));
+    }
+    if opts.threads {
+        columns.push(("THREADS", |entry| entry.cnt_threads.to_string()));
+    }
+    if opts.priority {
+        columns.push(("PRIORITY", |entry| entry.pc_pri_class_base.to_string()));
+    }
+    if opts.session {
+        columns.push(("SESSION", |entry| {
+            entry
+                .session_id()
+                .map_or_else(|_| "-".to_owned(), |id| id.to_string())
+        }));
+    }
+
+    let rows: Vec<Vec<_>> = processes
+        .iter()
+        .map(|(_, entry)| columns.iter().map(|(_, value)| value(entry)).collect())
+        .collect();
+    let widths: Vec<_> = columns
+        .iter()
+        .enumerate()
+        .map(|(col, (header, _))| {
+            rows.iter()
+                .map(|row| row[col].len())
+                .chain(Some(header.len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let headers = columns.iter().map(|(header, _)| *header);
+    write_row(w, &widths, headers, opts.name.then(|| (0, "NAME".into())))?;
+    for ((depth, entry), row) in processes.iter().zip(&rows) {
+        let name = opts
+            .name
+            .then(|| (*depth, entry.sz_exe_file.to_string_lossy()));
+        write_row(w, &widths, row.iter().map(String::as_str), name)?;
+    }
+    Ok(())
+}
+
+fn write_row<'a>(
+    w: &mut impl Write,
+    widths: &[usize],
+    cells: impl Iterator<Item = &'a str>,
+    name: Option<(usize, String)>,
+) -> Result<()> {
+    // writing into a `String` can't fail
+    let mut line = String::new();
+    for (cell, &width) in cells.zip(widths) {
+        if !line.is_empty() {
+            line.push_str("  ");
+        }
+        let _ = write!(line, "{:>width$}", cell, width = width);
+    }
+    if let Some((depth, name)) = name {
+        if !line.is_empty() {
+            line.push_str("  ");
+        }
+        let _ = write!(line, "{:indent$}{}", "", name, indent = depth * 2);
+    }
+    writeln!(w, "{}", line)
+}
@@ -9,21 +9,88 @@
     missing_debug_implementations
 )]
 
-use widestring::U16CString;
-use winapi::shared::minwindef::{BOOL, HMODULE, LPCVOID};
+use widestring::{U16CStr, U16CString};
+use winapi::shared::{
+    minwindef::{BOOL, FALSE, FARPROC, FILETIME, HMODULE, LPCVOID, MAX_PATH, TRUE},
+    winerror::{
+        ERROR_CALL_NOT_IMPLEMENTED, ERROR_INSUFFICIENT_BUFFER, ERROR_NOT_ALL_ASSIGNED,
+        ERROR_NO_MORE_FILES, ERROR_PARTIAL_COPY,
+    },
+};
 use winapi::um::{
+    errhandlingapi::{GetLastError, SetLastError},
     handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+    libloaderapi::{GetModuleHandleA, GetProcAddress},
+    minwinbase::LPTHREAD_START_ROUTINE,
+    processthreadsapi::{
+        CreateRemoteThread, GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId,
+        GetExitCodeThread, GetPriorityClass, GetProcessTimes, GetThreadContext, GetThreadTimes,
+        OpenProcess, OpenProcessToken, OpenThread, ProcessIdToSessionId, ResumeThread,
+        SetPriorityClass, SuspendThread, TerminateProcess,
+    },
+    securitybaseapi::{AdjustTokenPrivileges, GetTokenInformation},
+    stringapiset::CompareStringOrdinal,
+    synchapi::WaitForSingleObject,
+    sysinfoapi::{GetSystemDirectoryW, GetWindowsDirectoryW},
     tlhelp32::*,
-    winnt::HANDLE,
+    winbase::{
+        GetActiveProcessorCount, LookupPrivilegeValueW, QueryFullProcessImageNameW,
+        ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS,
+        IDLE_PRIORITY_CLASS, INFINITE, NORMAL_PRIORITY_CLASS, REALTIME_PRIORITY_CLASS, WAIT_FAILED,
+    },
+    winnls::LCMapStringEx,
+    winnt::{
+        TokenElevation, TokenPrivileges, ALL_PROCESSOR_GROUPS, CONTEXT, CONTEXT_FULL, HANDLE, LUID,
+        LUID_AND_ATTRIBUTES, PROCESS_CREATE_THREAD, PROCESS_QUERY_INFORMATION,
+        PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SET_INFORMATION, PROCESS_TERMINATE,
+        PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE, SE_PRIVILEGE_ENABLED,
+        THREAD_GET_CONTEXT, THREAD_QUERY_INFORMATION, THREAD_QUERY_LIMITED_INFORMATION,
+        THREAD_SUSPEND_RESUME, TOKEN_ADJUST_PRIVILEGES, TOKEN_ELEVATION, TOKEN_PRIVILEGES,
+        TOKEN_QUERY,
+    },
+    wow64apiset::IsWow64Process,
 };
 
 use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
+    ffi::{CString, OsString},
     fmt,
-    io::{Error, Result},
-    iter::{FusedIterator, Iterator},
-    mem,
+    fs::File,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Read, Result},
+    iter::{self, FromIterator, FusedIterator, Iterator, Skip},
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    os::windows::{
+        ffi::{OsStrExt, OsStringExt},
+        io::{AsRawHandle, FromRawHandle, IntoRawHandle, OwnedHandle},
+    },
+    path::{Path, PathBuf},
+    ptr,
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    vec,
 };
 
+mod dump;
+mod ntdll;
+mod pe;
+mod peb;
+mod reader;
+mod region;
+#[cfg(feature = "sha256")]
+mod sha256;
+mod watcher;
+
+pub use dump::{dump_process_table, dump_processes, ProcessDumpOptions};
+pub use pe::{Machine, PeInfo, PeSection};
+pub use reader::{ProcessMemory, ProcessReader, RangeRead};
+pub use region::{memory_regions, readable_regions, unbacked_executable_regions, MemoryRegion};
+pub use watcher::ProcessWatcher;
+
 type Tl32helpFunc<T> = unsafe extern "system" fn(HANDLE, *mut T) -> BOOL;
 
 macro_rules! to_u16cstring {
@@ -32,6 +99,217 @@ macro_rules! to_u16cstring {
     };
 }
 
+/// The process id that toolhelp interprets as the calling process when creating module and heap
+/// snapshots. Note that this is *not* the System process, whose id is `4` and which can't be
+/// snapshotted for modules or heaps.
+pub const CURRENT_PROCESS: u32 = 0;
+
+const SYSTEM_PROCESS: u32 = 4;
+
+/// The chunk size to pass to [`read_process_cstring`] when there is no reason to pick another one.
+pub const DEFAULT_CSTRING_CHUNK: usize = 256;
+
+/// The largest module [`ModuleEntry::read_image`] allocates a buffer for, 256 MiB.
+pub const DEFAULT_MAX_IMAGE_SIZE: usize = 256 * 1024 * 1024;
+
+pub(crate) fn open_process(access: u32, pid: u32) -> Result<OwnedHandle> {
+    let handle = unsafe { OpenProcess(access, FALSE, pid) };
+    if handle.is_null() {
+        Err(Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle as _) })
+    }
+}
+
+fn open_thread(access: u32, tid: u32) -> Result<OwnedHandle> {
+    let handle = unsafe { OpenThread(access, FALSE, tid) };
+    if handle.is_null() {
+        Err(Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle as _) })
+    }
+}
+
+pub(crate) fn raw(handle: &OwnedHandle) -> HANDLE {
+    handle.as_raw_handle() as HANDLE
+}
+
+const FILETIME_INTERVALS_PER_SEC: u64 = 10_000_000;
+
+fn filetime_intervals(time: FILETIME) -> u64 {
+    u64::from(time.dwHighDateTime) << 32 | u64::from(time.dwLowDateTime)
+}
+
+fn intervals_duration(intervals: u64) -> Duration {
+    Duration::new(
+        intervals / FILETIME_INTERVALS_PER_SEC,
+        (intervals % FILETIME_INTERVALS_PER_SEC * 100) as u32,
+    )
+}
+
+/// Converts a `FILETIME` holding a span of time, which counts 100 nanosecond intervals.
+fn duration(time: FILETIME) -> Duration {
+    intervals_duration(filetime_intervals(time))
+}
+
+/// Converts a `FILETIME` holding a point in time, which counts 100 nanosecond intervals since
+/// 1601-01-01 UTC.
+fn system_time(time: FILETIME) -> SystemTime {
+    const UNIX_EPOCH_INTERVALS: u64 = 116_444_736_000_000_000;
+    let intervals = filetime_intervals(time);
+    if intervals >= UNIX_EPOCH_INTERVALS {
+        UNIX_EPOCH + intervals_duration(intervals - UNIX_EPOCH_INTERVALS)
+    } else {
+        UNIX_EPOCH - intervals_duration(UNIX_EPOCH_INTERVALS - intervals)
+    }
+}
+
+pub(crate) fn process_creation_time(process: HANDLE) -> Result<SystemTime> {
+    Ok(system_time(process_times(process)?[0]))
+}
+
+/// The creation, exit, kernel and user times of the process, in this order.
+fn process_times(process: HANDLE) -> Result<[FILETIME; 4]> {
+    let mut times: [FILETIME; 4] = unsafe { mem::zeroed() };
+    let [creation, exit, kernel, user] = &mut times;
+    if unsafe { GetProcessTimes(process, creation, exit, kernel, user) } == 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(times)
+}
+
+pub(crate) fn is_wow64(process: HANDLE) -> Result<bool> {
+    let mut wow64 = 0;
+    if unsafe { IsWow64Process(process, &mut wow64) } == 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(wow64 != 0)
+    }
+}
+
+/// Checks whether the calling process runs under Wine, by looking for the `wine_get_version`
+/// export Wine's `ntdll.dll` has. The result is cached after the first call.
+///
+/// Wine implements toolhelp only partially. Heap snapshots in particular are stubbed, so
+/// [`Snapshot::new_heap_list`] reports the failures of the stub as [`ErrorKind::Unsupported`]
+/// there. The enrichment methods relying on undocumented `ntdll` functions, like
+/// [`ProcessEntry::environment`], might not work either.
+pub fn is_wine() -> bool {
+    static IS_WINE: OnceLock<bool> = OnceLock::new();
+    *IS_WINE.get_or_init(|| unsafe {
+        let ntdll = GetModuleHandleA(b"ntdll.dll\0".as_ptr() as *const _);
+        !ntdll.is_null()
+            && !GetProcAddress(ntdll, b"wine_get_version\0".as_ptr() as *const _).is_null()
+    })
+}
+
+fn open_own_token(access: u32) -> Result<OwnedHandle> {
+    let mut token = ptr::null_mut();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), access, &mut token) } == 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedHandle::from_raw_handle(token as _) })
+    }
+}
+
+fn debug_privilege_luid() -> Result<LUID> {
+    let name: Vec<u16> = "SeDebugPrivilege\0".encode_utf16().collect();
+    let mut luid = LUID {
+        LowPart: 0,
+        HighPart: 0,
+    };
+    if unsafe { LookupPrivilegeValueW(ptr::null(), name.as_ptr(), &mut luid) } == 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(luid)
+    }
+}
+
+/// Checks whether the calling process has the `SeDebugPrivilege` enabled, which grants it
+/// access to the processes of all users, including system processes, regardless of their
+/// security descriptors. Most enrichment methods, like [`ProcessEntry::environment`] or reading
+/// memory with a [`ProcessReader`], require it for processes of other users.
+/// Only elevated administrators hold the privilege, and even then it is disabled until it is
+/// enabled with [`enable_debug_privilege`]. Returns `false` if the token of the process can't be
+/// queried.
+pub fn has_debug_privilege() -> bool {
+    let query = || -> Result<bool> {
+        let luid = debug_privilege_luid()?;
+        let token = open_own_token(TOKEN_QUERY)?;
+        let mut len = 0;
+        unsafe { GetTokenInformation(raw(&token), TokenPrivileges, ptr::null_mut(), 0, &mut len) };
+        if unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+            return Err(Error::last_os_error());
+        }
+        // u64s to satisfy the alignment of `TOKEN_PRIVILEGES`
+        let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+        if unsafe {
+            GetTokenInformation(
+                raw(&token),
+                TokenPrivileges,
+                buf.as_mut_ptr() as *mut _,
+                len,
+                &mut len,
+            )
+        } == 0
+        {
+            return Err(Error::last_os_error());
+        }
+        let privileges = unsafe {
+            let privileges = &*(buf.as_ptr() as *const TOKEN_PRIVILEGES);
+            std::slice::from_raw_parts(
+                privileges.Privileges.as_ptr(),
+                privileges.PrivilegeCount as usize,
+            )
+        };
+        Ok(privileges.iter().any(|privilege| {
+            privilege.Luid.LowPart == luid.LowPart
+                && privilege.Luid.HighPart == luid.HighPart
+                && privilege.Attributes & SE_PRIVILEGE_ENABLED != 0
+        }))
+    };
+    query().unwrap_or(false)
+}
+
+/// Enables the `SeDebugPrivilege` in the token of the calling process, see
+/// [`has_debug_privilege`]. Tools scanning the processes of other users usually call this once
+/// on startup.
+/// The privilege can only be enabled if the token holds it, which requires the caller to run as
+/// an elevated administrator.
+/// # Errors
+/// This function fails and returns the appropriate os error if the token can't be adjusted, or
+/// with [`ErrorKind::PermissionDenied`] if the token doesn't hold the privilege.
+pub fn enable_debug_privilege() -> Result<()> {
+    let luid = debug_privilege_luid()?;
+    let token = open_own_token(TOKEN_ADJUST_PRIVILEGES)?;
+    let mut privileges = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: SE_PRIVILEGE_ENABLED,
+        }],
+    };
+    let res = unsafe {
+        AdjustTokenPrivileges(
+            raw(&token),
+            FALSE,
+            &mut privileges,
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    // succeeding doesn't mean the privilege was enabled, the last error tells
+    match (res, unsafe { GetLastError() }) {
+        (0, _) => Err(Error::last_os_error()),
+        (_, ERROR_NOT_ALL_ASSIGNED) => Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "the token doesn't hold the debug privilege, the process has to be elevated",
+        )),
+        _ => Ok(()),
+    }
+}
+
 /// Copies memory allocated to another process at the specified address into a supplied slice.
 /// The number of bytes to copy is the length of the supplied slice.
 pub fn read_process_memory(
@@ -56,6 +334,170 @@ pub fn read_process_memory(
     }
 }
 
+/// Like [`read_process_memory`], but takes the address as an integer, as addresses computed by
+/// pointer arithmetic usually are.
+pub fn read_process_memory_at(process_id: u32, address: usize, buffer: &mut [u8]) -> Result<usize> {
+    read_process_memory(process_id, address as LPCVOID, buffer)
+}
+
+/// Like [`read_process_memory`], but if the memory range is only partially readable, for example
+/// because it extends into a freed or paged out region, the readable prefix is copied into the
+/// supplied slice instead of failing outright.
+/// The length of the readable prefix is returned, it is found by binary searching on
+/// `ERROR_PARTIAL_COPY` failures.
+// the address is only ever dereferenced in the context of the other process
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn read_process_memory_partial(
+    process_id: u32,
+    base_address: LPCVOID,
+    buffer: &mut [u8],
+) -> Result<usize> {
+    let is_partial_copy = |e: &Error| e.raw_os_error() == Some(ERROR_PARTIAL_COPY as i32);
+    match read_process_memory(process_id, base_address, buffer) {
+        Err(ref e) if is_partial_copy(e) => (),
+        res => return res,
+    }
+    // `readable` bytes are known to be readable, `unreadable` bytes are known to fail
+    let (mut readable, mut unreadable) = (0, buffer.len());
+    while unreadable - readable > 1 {
+        let mid = readable + (unreadable - readable) / 2;
+        match read_process_memory(process_id, base_address, &mut buffer[..mid]) {
+            Ok(_) => readable = mid,
+            Err(ref e) if is_partial_copy(e) => unreadable = mid,
+            Err(e) => return Err(e),
+        }
+    }
+    if readable != 0 {
+        read_process_memory(process_id, base_address, &mut buffer[..readable])?;
+    }
+    Ok(readable)
+}
+
+/// Reads the NUL-terminated string at `address` in the memory of another process, `chunk` bytes
+/// at a time until the terminator is found.
+///
+/// The chunk size trades syscalls against over-reading: a string is read with
+/// `len / chunk + 1` reads, but every chunk except the last one is read in full, even past the
+/// terminator. A chunk that extends into an unmapped page fails with `ERROR_PARTIAL_COPY` and is
+/// retried as by [`read_process_memory_partial`], which takes several more reads. Small chunks
+/// thus suit many short strings, which are often placed near the end of their page, while large
+/// chunks suit long strings. [`DEFAULT_CSTRING_CHUNK`] is a sensible default for both.
+/// # Errors
+/// This function fails with [`ErrorKind::UnexpectedEof`] if the string runs into unreadable
+/// memory before the terminator, with [`ErrorKind::InvalidInput`] if `chunk` is `0`, and returns
+/// the appropriate os error if the memory can't be read for any other reason.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let name = tlhelp32::read_process_cstring(pid, address, tlhelp32::DEFAULT_CSTRING_CHUNK)?;
+/// ```
+pub fn read_process_cstring(process_id: u32, address: usize, chunk: usize) -> Result<CString> {
+    if chunk == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the chunk size is zero",
+        ));
+    }
+    let mut string = Vec::new();
+    let mut buf = vec![0; chunk];
+    loop {
+        let at = address.wrapping_add(string.len()) as LPCVOID;
+        let len = match read_process_memory_partial(process_id, at, &mut buf)? {
+            0 => {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "the string runs into unreadable memory",
+                ))
+            }
+            len => len,
+        };
+        match buf[..len].iter().position(|&b| b == 0) {
+            Some(nul) => {
+                string.extend_from_slice(&buf[..nul]);
+                // the string contains no interior NUL
+                return Ok(unsafe { CString::from_vec_unchecked(string) });
+            }
+            None => string.extend_from_slice(&buf[..len]),
+        }
+    }
+}
+
+/// Like [`read_process_memory`], but verifies that the process id of `identity` still refers to
+/// the process it was captured from before reading, which protects scan-then-read workflows
+/// from reading a process that reused the id of an exited one.
+/// Unlike [`read_process_memory`] this opens the process, see
+/// [`ProcessReader::open_verified`], and the same handle is used for the verification and the
+/// read, so the process can't be swapped in between. The verification costs an additional open
+/// and `GetProcessTimes` call per read, for many reads open a single verified reader instead.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to open the process
+/// or to read its memory, or with a [`ProcessMismatch`] if the process id was reused.
+// the address is only ever dereferenced in the context of the other process
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn read_process_memory_verified(
+    identity: &ProcessIdentity,
+    base_address: LPCVOID,
+    buffer: &mut [u8],
+) -> Result<usize> {
+    ProcessReader::open_verified(identity)?.read(base_address, buffer)
+}
+
+/// Checks that the toolhelp structs this crate was compiled with have the sizes the Windows SDK
+/// documents. The OS rejects entries whose `dwSize` it doesn't expect, which makes every snapshot
+/// look empty instead of failing, so a mismatch here explains such a confusing failure.
+///
+/// The expected sizes in bytes are:
+///
+/// | struct            | 32-bit | 64-bit |
+/// |-------------------|--------|--------|
+/// | `PROCESSENTRY32W` | 556    | 568    |
+/// | `MODULEENTRY32W`  | 1064   | 1080   |
+/// | `THREADENTRY32`   | 28     | 28     |
+/// | `HEAPLIST32`      | 16     | 32     |
+/// | `HEAPENTRY32`     | 36     | 56     |
+///
+/// This is never called implicitly, so it costs nothing unless used.
+/// # Errors
+/// This function fails with [`ErrorKind::InvalidData`] naming the first struct whose size doesn't
+/// match.
+pub fn validate_sizes() -> Result<()> {
+    let expected: [(&str, usize, usize, usize); 5] = [
+        (
+            "PROCESSENTRY32W",
+            mem::size_of::<PROCESSENTRY32W>(),
+            556,
+            568,
+        ),
+        (
+            "MODULEENTRY32W",
+            mem::size_of::<MODULEENTRY32W>(),
+            1064,
+            1080,
+        ),
+        ("THREADENTRY32", mem::size_of::<THREADENTRY32>(), 28, 28),
+        ("HEAPLIST32", mem::size_of::<HEAPLIST32>(), 16, 32),
+        ("HEAPENTRY32", mem::size_of::<HEAPENTRY32>(), 36, 56),
+    ];
+    for &(name, actual, size_32, size_64) in &expected {
+        let expected = if cfg!(target_pointer_width = "64") {
+            size_64
+        } else {
+            size_32
+        };
+        if actual != expected {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "`{}` is {} bytes large, but the SDK expects {} bytes",
+                    name, actual, expected
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// A trait for the different [`Snapshot`] types. You shouldn't need to work with this directly.
 pub trait TagTl32: private::Sealed {
     /// The raw windows counterpart of the implementing struct
@@ -72,6 +514,24 @@ pub trait TagTl32: private::Sealed {
 
     /// Creates a new instance of `Self` from its windows counterpart.
     fn from_raw(raw: Self::Raw) -> Self;
+
+    /// Creates the windows counterpart of `self`, as toolhelp would have filled it in. Mocked
+    /// snapshots walk these.
+    #[doc(hidden)]
+    fn to_raw(&self) -> Self::Raw;
+
+    /// Marks `self` as the first entry of its snapshot. Only module entries record this.
+    #[doc(hidden)]
+    #[inline]
+    fn mark_first(&mut self) {}
+}
+
+/// Copies `src` into the fixed size buffer of a raw entry, truncating it to keep the NUL
+/// terminator.
+fn copy_wide(dst: &mut [u16], src: &U16CStr) {
+    let len = src.len().min(dst.len() - 1);
+    dst[..len].copy_from_slice(&src.as_slice()[..len]);
+    dst[len] = 0;
 }
 
 mod private {
@@ -87,10 +547,14 @@ mod private {
 #[allow(missing_docs)]
 #[derive(Clone)]
 pub struct ProcessEntry {
+    cnt_usage: u32,
     pub process_id: u32,
+    default_heap_id: usize,
+    module_id: u32,
     pub cnt_threads: u32,
     pub parent_process_id: u32,
     pub pc_pri_class_base: i32,
+    flags: u32,
     pub sz_exe_file: U16CString,
 }
 
@@ -111,22 +575,47 @@ impl TagTl32 for ProcessEntry {
     #[inline]
     fn from_raw(raw: Self::Raw) -> Self {
         ProcessEntry {
+            cnt_usage: raw.cntUsage,
             process_id: raw.th32ProcessID,
+            default_heap_id: raw.th32DefaultHeapID,
+            module_id: raw.th32ModuleID,
             cnt_threads: raw.cntThreads,
             parent_process_id: raw.th32ParentProcessID,
             pc_pri_class_base: raw.pcPriClassBase,
+            flags: raw.dwFlags,
             sz_exe_file: to_u16cstring!(raw.szExeFile),
         }
     }
+
+    fn to_raw(&self) -> Self::Raw {
+        let mut raw = Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>() as u32,
+            cntUsage: self.cnt_usage,
+            th32ProcessID: self.process_id,
+            th32DefaultHeapID: self.default_heap_id,
+            th32ModuleID: self.module_id,
+            cntThreads: self.cnt_threads,
+            th32ParentProcessID: self.parent_process_id,
+            pcPriClassBase: self.pc_pri_class_base,
+            dwFlags: self.flags,
+            szExeFile: [0; MAX_PATH],
+        };
+        copy_wide(&mut raw.szExeFile, &self.sz_exe_file);
+        raw
+    }
 }
 
 impl fmt::Debug for ProcessEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ProcessEntry")
+            .field("cnt_usage", &self.cnt_usage)
             .field("process_id", &self.process_id)
+            .field("default_heap_id", &self.default_heap_id)
+            .field("module_id", &self.module_id)
             .field("cnt_threads", &self.cnt_threads)
             .field("parent_process_id", &self.parent_process_id)
             .field("pc_pri_class_base", &self.pc_pri_class_base)
+            .field("flags", &self.flags)
             .field(
                 "sz_exe_file",
                 &self.sz_exe_file.to_string().unwrap_or_default(),
@@ -135,318 +624,3549 @@ impl fmt::Debug for ProcessEntry {
     }
 }
 
-/// A module entry taken from a [`Snapshot`].
-/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagmoduleentry32)
-#[allow(missing_docs)]
-#[derive(Clone)]
-pub struct ModuleEntry {
-    pub process_id: u32,
-    pub base_addr: *mut u8,
-    pub base_size: u32,
-    pub h_module: HMODULE,
-    pub sz_module: U16CString,
-    pub sz_exe_path: U16CString,
+/// Borrows the executable name, for comparing it against wide strings without allocating.
+impl AsRef<U16CStr> for ProcessEntry {
+    fn as_ref(&self) -> &U16CStr {
+        &self.sz_exe_file
+    }
 }
 
-impl TagTl32 for ModuleEntry {
-    type Raw = MODULEENTRY32W;
-    const FLAGS: u32 = TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32;
-    const ITER_FIRST: Tl32helpFunc<Self::Raw> = Module32FirstW;
-    const ITER_NEXT: Tl32helpFunc<Self::Raw> = Module32NextW;
-
-    #[inline]
-    fn init_raw() -> Self::Raw {
-        Self::Raw {
-            dwSize: mem::size_of::<Self::Raw>() as u32,
-            ..unsafe { mem::uninitialized() }
-        }
+/// Two process entries are equal if they have the same process id, the remaining fields are
+/// ignored, which makes the comparison consistent with the one against a plain `u32`. Note that
+/// entries of snapshots taken at different times can have the same id but belong to different
+/// processes, if the id has been reused in between.
+impl PartialEq for ProcessEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.process_id == other.process_id
     }
+}
 
-    #[inline]
-    fn from_raw(raw: Self::Raw) -> Self {
-        ModuleEntry {
-            process_id: raw.th32ProcessID,
-            base_addr: raw.modBaseAddr,
-            base_size: raw.modBaseSize,
-            h_module: raw.hModule,
-            sz_module: to_u16cstring!(raw.szModule),
-            sz_exe_path: to_u16cstring!(raw.szExePath),
-        }
+impl Eq for ProcessEntry {}
+
+/// Hashes the process id only, like equality compares it.
+impl Hash for ProcessEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.process_id.hash(state);
     }
 }
 
-impl fmt::Debug for ModuleEntry {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("ProcessEntry")
-            .field("process_id", &self.process_id)
-            .field("base_addr", &self.base_addr)
-            .field("base_size", &self.base_size)
-            .field("h_module", &self.h_module)
-            .field("sz_module", &self.sz_module.to_string().unwrap_or_default())
-            .field(
-                "sz_exe_file",
-                &self.sz_exe_path.to_string().unwrap_or_default(),
-            )
-            .finish()
+/// Compares the process id, so looking up a process reads as `entry == pid`.
+impl PartialEq<u32> for ProcessEntry {
+    fn eq(&self, pid: &u32) -> bool {
+        self.process_id == *pid
     }
 }
 
-/// A heap list taken from a [`Snapshot`]. This struct is an iterator over the heap entries of its heap.
-/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagheaplist32)
-#[allow(missing_docs, missing_copy_implementations)]
-pub struct HeapList {
-    pub process_id: u32,
-    pub heap_id: usize,
-    pub flags: u32,
-    current: Option<HEAPENTRY32>,
+/// Compares the process id, so that `pid == entry` holds whenever `entry == pid` does.
+impl PartialEq<ProcessEntry> for u32 {
+    fn eq(&self, entry: &ProcessEntry) -> bool {
+        *self == entry.process_id
+    }
 }
 
-impl TagTl32 for HeapList {
-    type Raw = HEAPLIST32;
-    const FLAGS: u32 = TH32CS_SNAPHEAPLIST;
-    const ITER_FIRST: Tl32helpFunc<Self::Raw> = Heap32ListFirst;
-    const ITER_NEXT: Tl32helpFunc<Self::Raw> = Heap32ListNext;
+impl ProcessEntry {
+    /// Converts a raw `PROCESSENTRY32W` obtained from your own toolhelp calls. The caller has to pass a
+    /// struct that was fully populated by a successful `*32First`/`*32Next` call.
+    pub fn from_windows(raw: PROCESSENTRY32W) -> Self {
+        ProcessEntry::from_raw(raw)
+    }
 
-    #[inline]
-    fn init_raw() -> Self::Raw {
-        Self::Raw {
-            dwSize: mem::size_of::<Self::Raw>(),
-            ..unsafe { mem::uninitialized() }
-        }
+    /// The `cntUsage` of the process. This is always `0`, the field is no longer used by Windows.
+    pub fn usage_count(&self) -> u32 {
+        self.cnt_usage
     }
 
-    #[inline]
-    fn from_raw(raw: Self::Raw) -> Self {
-        let mut entry = HEAPENTRY32 {
-            dwSize: mem::size_of::<HEAPENTRY32>(),
-            ..unsafe { mem::uninitialized() }
-        };
-        let current = if unsafe { Heap32First(&mut entry, raw.th32ProcessID, raw.th32HeapID) == 0 }
-        {
-            None
-        } else {
-            Some(entry)
-        };
-        HeapList {
-            process_id: raw.th32ProcessID,
-            heap_id: raw.th32HeapID,
-            flags: raw.dwFlags,
-            current,
-        }
+    /// The `th32DefaultHeapID` of the process. This is always `0` on current Windows versions,
+    /// older ones reported the id of the process's default heap, as found in a heap list
+    /// [`Snapshot`].
+    pub fn default_heap_id(&self) -> usize {
+        self.default_heap_id
     }
-}
 
-impl Iterator for HeapList {
-    type Item = HeapEntry;
-    fn next(&mut self) -> Option<Self::Item> {
-        let val = HeapEntry::from_raw(self.current?);
-        if unsafe { Heap32Next(self.current.as_mut().unwrap()) == 0 } {
-            self.current = None
-        }
-        Some(val)
+    /// The `th32ModuleID` of the process. This is always `0`, the field is no longer used by
+    /// Windows.
+    pub fn module_id(&self) -> u32 {
+        self.module_id
     }
-}
 
-impl fmt::Debug for HeapList {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("HeapList")
-            .field("process_id", &self.process_id)
-            .field("heap_id", &self.heap_id)
-            .field("flags", &self.flags)
-            .field("exhausted", &self.current.is_none())
-            .finish()
+    /// The `dwFlags` of the process. This is always `0`, the field is no longer used by Windows.
+    pub fn flags(&self) -> u32 {
+        self.flags
     }
-}
 
-/// A heap entry taken from a [`HeapList`].
-/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagheapentry32)
-#[allow(missing_docs)]
-#[derive(Clone, Copy, Debug)]
-pub struct HeapEntry {
-    pub handle: HANDLE,
-    pub address: usize,
-    pub block_size: usize,
-    pub flags: u32,
-    pub process_id: u32,
-    pub heap_id: usize,
-}
+    /// Reads the environment variables of the process as `(key, value)` pairs by walking its PEB.
+    /// Variables that aren't valid UTF-16 are converted lossily. The hidden per-drive variables
+    /// like `=C:` are included with their leading `=` as part of the key.
+    ///
+    /// This opens the process with the `PROCESS_VM_READ` and
+    /// `PROCESS_QUERY_LIMITED_INFORMATION` access rights.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the process can't be opened,
+    /// which is the case for protected processes, or if its memory can't be read. A 32-bit caller
+    /// can't read the environment of a 64-bit process and gets an [`ErrorKind::Unsupported`] error.
+    pub fn environment(&self) -> Result<Vec<(String, String)>> {
+        let reader = ProcessReader::open(self.process_id)?;
+        let block = peb::ProcessParameters::locate(&reader)?.environment()?;
+        Ok(peb::parse_environment(&block))
+    }
 
-impl HeapEntry {
-    fn from_raw(raw: HEAPENTRY32) -> Self {
-        HeapEntry {
-            handle: raw.hHandle,
-            address: raw.dwAddress,
-            block_size: raw.dwBlockSize,
-            flags: raw.dwFlags,
-            process_id: raw.th32ProcessID,
-            heap_id: raw.th32HeapID,
+    /// Reads the command line the process was started with by walking its PEB. Characters that
+    /// aren't valid UTF-16 are converted lossily.
+    /// The command line is read from the memory of the process, which is free to overwrite it.
+    ///
+    /// This opens the process with the `PROCESS_VM_READ` and
+    /// `PROCESS_QUERY_LIMITED_INFORMATION` access rights.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the process can't be opened,
+    /// which is the case for protected processes, or if its memory can't be read. A 32-bit caller
+    /// can't read the command line of a 64-bit process and gets an [`ErrorKind::Unsupported`]
+    /// error.
+    pub fn command_line(&self) -> Result<String> {
+        let reader = ProcessReader::open(self.process_id)?;
+        let line = peb::ProcessParameters::locate(&reader)?.command_line()?;
+        Ok(String::from_utf16_lossy(&line))
+    }
+
+    /// Reads the current working directory of the process by walking its PEB. The path is the
+    /// one the process last set, including its trailing backslash.
+    ///
+    /// This opens the process with the `PROCESS_VM_READ` and
+    /// `PROCESS_QUERY_LIMITED_INFORMATION` access rights.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the process can't be opened,
+    /// which is the case for protected processes, or if its memory can't be read. A 32-bit caller
+    /// can't read the directory of a 64-bit process and gets an [`ErrorKind::Unsupported`] error.
+    /// If the directory stored in the process is malformed, for example because the process is
+    /// still being initialized, [`ErrorKind::InvalidData`] is returned.
+    pub fn current_directory(&self) -> Result<PathBuf> {
+        let reader = ProcessReader::open(self.process_id)?;
+        let dir = peb::ProcessParameters::locate(&reader)?.current_directory()?;
+        Ok(PathBuf::from(OsString::from_wide(&dir)))
+    }
+
+    /// Checks whether a module with the given name is loaded in the process. The names are
+    /// compared ignoring case the way Windows compares file names, see [`wide_eq_ignore_case`].
+    /// The module snapshot is only walked until the first match.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to create a
+    /// module [`Snapshot`] of the process
+    pub fn has_module(&self, name: &str) -> Result<bool> {
+        let name = match U16CString::from_str(name) {
+            Ok(name) => name,
+            // no module name contains a nul
+            Err(_) => return Ok(false),
+        };
+        Ok(Snapshot::new_module(self.process_id)?
+            .any(|entry| wide_eq_ignore_case(&entry.sz_module, &name)))
+    }
+
+    /// Retrieves the id of the Remote Desktop Services session the process belongs to.
+    /// This doesn't require opening the process.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the process no longer exists
+    pub fn session_id(&self) -> Result<u32> {
+        let mut session_id = 0;
+        if unsafe { ProcessIdToSessionId(self.process_id, &mut session_id) } == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(session_id)
         }
     }
-}
 
-/// A thread entry taken from a [`Snapshot`].
-/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagthreadentry32)
+    /// Checks whether the process runs elevated, that is with the full administrator token
+    /// rather than a filtered one.
+    ///
+    /// This opens the process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right and its
+    /// token with `TOKEN_QUERY`. Querying processes of other users usually requires the caller to
+    /// be elevated itself.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or its token, access is denied for processes of higher privilege.
+    pub fn is_elevated(&self) -> Result<bool> {
+        let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, self.process_id)?;
+        let mut token = ptr::null_mut();
+        if unsafe { OpenProcessToken(raw(&process), TOKEN_QUERY, &mut token) } == 0 {
+            return Err(Error::last_os_error());
+        }
+        let token = unsafe { OwnedHandle::from_raw_handle(token as _) };
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut len = 0;
+        if unsafe {
+            GetTokenInformation(
+                raw(&token),
+                TokenElevation,
+                &mut elevation as *mut _ as *mut _,
+                mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut len,
+            )
+        } == 0
+        {
+            return Err(Error::last_os_error());
+        }
+        Ok(elevation.TokenIsElevated != 0)
+    }
+
+    /// Returns the ancestors of the process, from its immediate parent up to the first process
+    /// whose parent isn't running anymore, all taken from a single process [`Snapshot`].
+    ///
+    /// Windows doesn't update `parent_process_id` when the parent exits, so a reused id can make
+    /// an unrelated process show up as an ancestor, and in rare cases form a cycle. The walk stops
+    /// at the first process it has already visited, and after at most 256 steps.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+    pub fn ancestors(&self) -> Result<Vec<ProcessEntry>> {
+        const MAX_DEPTH: usize = 256;
+
+        let mut processes: HashMap<_, _> = Snapshot::new_process()?
+            .map(|entry| (entry.process_id, entry))
+            .collect();
+        let mut visited = HashSet::new();
+        visited.insert(self.process_id);
+        let mut ancestors = Vec::new();
+        let mut parent = self.parent_process_id;
+        while ancestors.len() < MAX_DEPTH && visited.insert(parent) {
+            match processes.remove(&parent) {
+                Some(entry) => {
+                    parent = entry.parent_process_id;
+                    ancestors.push(entry);
+                }
+                None => break,
+            }
+        }
+        Ok(ancestors)
+    }
+
+    /// Checks whether the process is a 32-bit process running under WOW64 on 64-bit Windows.
+    /// This is `false` for native processes of either bitness, including all processes on 32-bit
+    /// Windows.
+    ///
+    /// This opens the process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    pub fn is_wow64(&self) -> Result<bool> {
+        let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, self.process_id)?;
+        is_wow64(raw(&process))
+    }
+
+    /// Retrieves the full path of the process's executable image. Unlike `sz_exe_file`, which only
+    /// holds the file name, this isn't truncated to `MAX_PATH`.
+    ///
+    /// This opens the process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to query its image name
+    pub fn full_image_path(&self) -> Result<PathBuf> {
+        let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, self.process_id)?;
+        // the maximum length of an extended-length path
+        let mut buf = vec![0u16; 0x8000];
+        let mut len = buf.len() as u32;
+        if unsafe { QueryFullProcessImageNameW(raw(&process), 0, buf.as_mut_ptr(), &mut len) } == 0
+        {
+            return Err(Error::last_os_error());
+        }
+        Ok(OsString::from_wide(&buf[..len as usize]).into())
+    }
+
+    /// Retrieves the time the process was created at.
+    ///
+    /// This opens the process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to query its times
+    pub fn creation_time(&self) -> Result<SystemTime> {
+        let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, self.process_id)?;
+        process_creation_time(raw(&process))
+    }
+
+    /// Checks whether this process was created before `other`, for example to pick the original
+    /// instance out of several processes with the same name. The creation times are compared as
+    /// the raw `FILETIME` counts, without converting them to [`SystemTime`]s like
+    /// [`creation_time`](Self::creation_time) does.
+    /// Both processes still have to be opened and queried, and the comparison is only meaningful
+    /// while both are running, as their process ids might have been reused otherwise.
+    ///
+    /// This opens both processes with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open either
+    /// process or to query its times
+    pub fn started_before(&self, other: &ProcessEntry) -> Result<bool> {
+        let creation = |pid| -> Result<u64> {
+            let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, pid)?;
+            Ok(filetime_intervals(process_times(raw(&process))?[0]))
+        };
+        Ok(creation(self.process_id)? < creation(other.process_id)?)
+    }
+
+    /// Queries the current priority class of the process. Unlike `pc_pri_class_base`, which is the
+    /// base priority at the time of the snapshot, this is the live value.
+    ///
+    /// This opens the process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to query its priority class
+    pub fn priority_class(&self) -> Result<PriorityClass> {
+        let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, self.process_id)?;
+        match unsafe { GetPriorityClass(raw(&process)) } {
+            0 => Err(Error::last_os_error()),
+            class => Ok(class.into()),
+        }
+    }
+
+    /// Changes the priority class of the process.
+    /// Setting [`PriorityClass::Realtime`] requires the caller to hold the
+    /// `SeIncreaseBasePriorityPrivilege`, without it the class is silently set to
+    /// [`PriorityClass::High`] instead.
+    ///
+    /// This opens the process with the `PROCESS_SET_INFORMATION` access right, which is usually
+    /// only granted for processes of other users if the caller is elevated.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to set its priority class, access is denied for processes of higher privilege.
+    pub fn set_priority_class(&self, class: PriorityClass) -> Result<()> {
+        let process = open_process(PROCESS_SET_INFORMATION, self.process_id)?;
+        if unsafe { SetPriorityClass(raw(&process), class.into()) } == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Terminates the process and all of its descendants with the given exit code, like "End
+    /// process tree" in the Task Manager does, and returns which of them were terminated.
+    /// The descendants are found through [`process_tree`] and terminated from the leaves up,
+    /// children before their parents. The termination is partial if some of the processes fail
+    /// to terminate, because access to them is denied or because they exited already: they are
+    /// recorded in [`failures`](TreeTermination::failures) and the others are terminated
+    /// regardless, so check [`is_complete`](TreeTermination::is_complete).
+    ///
+    /// **This is destructive.** Terminated processes get no chance to save their state or clean
+    /// up, and anything they were writing may be left corrupted. If the calling process is one of
+    /// the descendants, it terminates itself midway.
+    /// The tree is only a snapshot: processes started after it was taken survive, and as process
+    /// ids are reused, a process that exited after the snapshot might have passed its id on to an
+    /// unrelated process, which is then terminated instead. Likewise a process whose parent id was
+    /// reused by this process is considered its child.
+    ///
+    /// This opens the processes with the `PROCESS_TERMINATE` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to create the
+    /// process [`Snapshot`], in which case no process has been terminated.
+    pub fn terminate_tree(&self, exit_code: u32) -> Result<TreeTermination> {
+        let tree = process_tree()?;
+        let pids: Vec<u32> = match tree
+            .iter()
+            .position(|(_, entry)| entry.process_id == self.process_id)
+        {
+            Some(root) => {
+                let depth = tree[root].0;
+                iter::once(self.process_id)
+                    .chain(
+                        tree[root + 1..]
+                            .iter()
+                            .take_while(|&&(d, _)| d > depth)
+                            .map(|(_, entry)| entry.process_id),
+                    )
+                    .collect()
+            }
+            None => vec![self.process_id],
+        };
+        let terminate = |pid| -> Result<()> {
+            let process = open_process(PROCESS_TERMINATE, pid)?;
+            if unsafe { TerminateProcess(raw(&process), exit_code) } == 0 {
+                Err(Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        };
+        let mut result = TreeTermination::default();
+        // the tree is in pre-order, so children come after their parents
+        for pid in pids.into_iter().rev() {
+            match terminate(pid) {
+                Ok(()) => result.terminated.push(pid),
+                Err(e) => result.failures.push((pid, e)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Captures the identity of the process, its id together with its creation time, to verify
+    /// later on that the id still refers to the same process, see [`ProcessIdentity`].
+    ///
+    /// This opens the process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to query its times
+    pub fn identity(&self) -> Result<ProcessIdentity> {
+        Ok(ProcessIdentity {
+            process_id: self.process_id,
+            creation_time: self.creation_time()?,
+        })
+    }
+
+    /// Compares the PE headers of the process's main module in memory against those of its
+    /// executable on disk, as found through [`full_image_path`](Self::full_image_path). The entry
+    /// point and the section headers are compared.
+    /// Returns `Ok(false)` on a mismatch, which suggests that the image has been hollowed out
+    /// and replaced.
+    ///
+    /// Relocations and self-modifying code don't touch the compared fields, yet a mismatch isn't
+    /// proof of tampering: packers and some protection software legitimately rewrite the headers
+    /// of their own image, and the file on disk might have been replaced after the process started.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to create a module
+    /// [`Snapshot`] of the process, to read its memory or to read the file, or with
+    /// [`ErrorKind::InvalidData`] if either image doesn't have valid PE headers.
+    pub fn image_matches_disk(&self) -> Result<bool> {
+        let main = Snapshot::new_module(self.process_id)?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "the process has no modules"))?;
+        let mut memory = vec![0; pe::HEADER_SIZE];
+        let len = read_process_memory(self.process_id, main.base_addr as _, &mut memory)?;
+        memory.truncate(len);
+        let mut disk = Vec::with_capacity(pe::HEADER_SIZE);
+        File::open(self.full_image_path()?)?
+            .take(pe::HEADER_SIZE as u64)
+            .read_to_end(&mut disk)?;
+
+        let (memory, disk) = (pe::Headers::parse(&memory)?, pe::Headers::parse(&disk)?);
+        Ok(memory.entry_point == disk.entry_point
+            && memory.section_headers().eq(disk.section_headers()))
+    }
+}
+
+/// The outcome of [`ProcessEntry::terminate_tree`].
+#[derive(Debug, Default)]
+pub struct TreeTermination {
+    /// The processes that were terminated, children before their parents.
+    pub terminated: Vec<u32>,
+    /// The processes that couldn't be terminated, together with the reason, in the order they
+    /// were attempted.
+    pub failures: Vec<(u32, Error)>,
+}
+
+impl TreeTermination {
+    /// Checks whether every process of the tree was terminated.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The priority class of a process, see [`ProcessEntry::priority_class`].
 #[allow(missing_docs)]
-#[derive(Clone, Copy, Debug)]
-pub struct ThreadEntry {
-    pub thread_id: u32,
-    pub owner_process_id: u32,
-    pub base_pri: i32,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PriorityClass {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+    Realtime,
+    /// Any other `*_PRIORITY_CLASS` value.
+    Other(u32),
+}
+
+impl From<u32> for PriorityClass {
+    fn from(class: u32) -> Self {
+        match class {
+            IDLE_PRIORITY_CLASS => PriorityClass::Idle,
+            BELOW_NORMAL_PRIORITY_CLASS => PriorityClass::BelowNormal,
+            NORMAL_PRIORITY_CLASS => PriorityClass::Normal,
+            ABOVE_NORMAL_PRIORITY_CLASS => PriorityClass::AboveNormal,
+            HIGH_PRIORITY_CLASS => PriorityClass::High,
+            REALTIME_PRIORITY_CLASS => PriorityClass::Realtime,
+            other => PriorityClass::Other(other),
+        }
+    }
+}
+
+impl From<PriorityClass> for u32 {
+    fn from(class: PriorityClass) -> Self {
+        match class {
+            PriorityClass::Idle => IDLE_PRIORITY_CLASS,
+            PriorityClass::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            PriorityClass::Normal => NORMAL_PRIORITY_CLASS,
+            PriorityClass::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+            PriorityClass::High => HIGH_PRIORITY_CLASS,
+            PriorityClass::Realtime => REALTIME_PRIORITY_CLASS,
+            PriorityClass::Other(other) => other,
+        }
+    }
+}
+
+/// An owned summary of a [`ProcessEntry`] with its executable name decoded into a [`String`].
+/// Unlike [`Snapshot`] this type holds no handle, so it can be freely sent across threads.
+#[allow(missing_docs)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessInfo {
+    pub process_id: u32,
+    pub cnt_threads: u32,
+    pub parent_process_id: u32,
+    pub pc_pri_class_base: i32,
+    pub exe_file: String,
+}
+
+impl From<ProcessEntry> for ProcessInfo {
+    fn from(entry: ProcessEntry) -> Self {
+        ProcessInfo {
+            process_id: entry.process_id,
+            cnt_threads: entry.cnt_threads,
+            parent_process_id: entry.parent_process_id,
+            pc_pri_class_base: entry.pc_pri_class_base,
+            exe_file: entry.sz_exe_file.to_string_lossy(),
+        }
+    }
+}
+
+/// Everything [`process_details`] gathers about a process. Every enrichment is queried on its
+/// own, so each field holds either the value or the reason it couldn't be queried.
+///
+/// The fields queried with `PROCESS_QUERY_LIMITED_INFORMATION`, `image_path`, `is_wow64` and
+/// `creation_time`, are available for most processes, although those of other users usually
+/// require the caller to be elevated. `command_line` additionally requires `PROCESS_VM_READ`,
+/// which is denied for protected processes even to elevated callers unless they have enabled
+/// `SeDebugPrivilege`. `session_id` requires no access to the process at all.
+#[derive(Debug)]
+pub struct ProcessDetails {
+    /// The snapshot entry of the process.
+    pub entry: ProcessEntry,
+    /// See [`ProcessEntry::full_image_path`].
+    pub image_path: Result<PathBuf>,
+    /// See [`ProcessEntry::command_line`].
+    pub command_line: Result<String>,
+    /// See [`ProcessEntry::session_id`].
+    pub session_id: Result<u32>,
+    /// See [`ProcessEntry::is_wow64`].
+    pub is_wow64: Result<bool>,
+    /// See [`ProcessEntry::creation_time`].
+    pub creation_time: Result<SystemTime>,
+}
+
+/// A process id together with the creation time of the process, which identifies a process
+/// across process id reuse: once a process exits its id can be given to a new process, which
+/// then has a different creation time.
+/// Captured by [`ProcessEntry::identity`] and verified by [`ProcessReader::open_verified`] and
+/// [`read_process_memory_verified`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessIdentity {
+    /// The id of the process.
+    pub process_id: u32,
+    /// The time the process was created at.
+    pub creation_time: SystemTime,
+}
+
+/// The error a [`ProcessIdentity`] fails to verify with, because its process exited and its id
+/// now belongs to another process. It is wrapped in an [`Error`] of kind
+/// [`ErrorKind::NotFound`] and can be retrieved with [`Error::get_ref`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessMismatch {
+    /// The identity that was expected.
+    pub expected: ProcessIdentity,
+    /// The creation time of the process that has the id now.
+    pub actual_creation_time: SystemTime,
+}
+
+impl fmt::Display for ProcessMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the process id {} was reused by another process",
+            self.expected.process_id
+        )
+    }
+}
+
+impl std::error::Error for ProcessMismatch {}
+
+/// The error a [`ProcessMemory`] with [`check_alive`](ProcessMemory::check_alive) enabled fails
+/// with once its process has exited. It is wrapped in an [`Error`] of kind
+/// [`ErrorKind::NotFound`] and can be retrieved with [`Error::get_ref`], which tells it apart from
+/// a [`ProcessMismatch`].
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let reader = tlhelp32::ProcessReader::open(pid)?.check_alive(true);
+/// if let Err(e) = reader.read(address, &mut buf) {
+///     if e.get_ref().is_some_and(|e| e.is::<tlhelp32::ProcessGone>()) {
+///         println!("process {} has exited", pid);
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessGone {
+    /// The id the process had.
+    pub process_id: u32,
+}
+
+impl fmt::Display for ProcessGone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the process {} has exited", self.process_id)
+    }
+}
+
+impl std::error::Error for ProcessGone {}
+
+/// The processes of a [`Snapshot`] together with their executable names uppercased ahead of time,
+/// for matching them by name repeatedly without case folding every entry per query. This takes
+/// an additional uppercased copy of every name.
+/// The names are uppercased with the invariant casing table, like [`wide_eq_ignore_case`]
+/// compares them, so names match the same processes as with [`find_process_by_name`].
+/// Created by [`Snapshot::index_by_name`].
+#[derive(Clone, Debug)]
+pub struct ProcessEntryIndexed {
+    entries: Vec<(Vec<u16>, ProcessEntry)>,
+}
+
+impl ProcessEntryIndexed {
+    /// Returns the first process whose executable name matches `name`, ignoring case as
+    /// described for [`wide_eq_ignore_case`].
+    pub fn find(&self, name: &str) -> Option<&ProcessEntry> {
+        self.find_all(name).next()
+    }
+
+    /// Returns all processes whose executable name matches `name`, ignoring case as described
+    /// for [`wide_eq_ignore_case`].
+    pub fn find_all<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a ProcessEntry> {
+        let name = wide_uppercase(&name.encode_utf16().collect::<Vec<_>>());
+        self.entries
+            .iter()
+            .filter(move |(uppercase, _)| *uppercase == name)
+            .map(|(_, entry)| entry)
+    }
+
+    /// Returns all indexed processes in snapshot order.
+    pub fn entries(&self) -> impl Iterator<Item = &ProcessEntry> {
+        self.entries.iter().map(|(_, entry)| entry)
+    }
+
+    /// The number of indexed processes.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no processes were indexed.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A module entry taken from a [`Snapshot`].
+/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagmoduleentry32)
+#[allow(missing_docs)]
+#[derive(Clone)]
+pub struct ModuleEntry {
+    module_id: u32,
+    pub process_id: u32,
+    glblcnt_usage: u32,
+    proccnt_usage: u32,
+    pub base_addr: *mut u8,
+    pub base_size: u32,
+    pub h_module: HMODULE,
+    pub sz_module: U16CString,
+    pub sz_exe_path: U16CString,
+    is_main: bool,
+}
+
+impl TagTl32 for ModuleEntry {
+    type Raw = MODULEENTRY32W;
+    const FLAGS: u32 = TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32;
+    const ITER_FIRST: Tl32helpFunc<Self::Raw> = Module32FirstW;
+    const ITER_NEXT: Tl32helpFunc<Self::Raw> = Module32NextW;
+
+    #[inline]
+    fn init_raw() -> Self::Raw {
+        Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>() as u32,
+            ..unsafe { mem::uninitialized() }
+        }
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw) -> Self {
+        ModuleEntry {
+            module_id: raw.th32ModuleID,
+            process_id: raw.th32ProcessID,
+            glblcnt_usage: raw.GlblcntUsage,
+            proccnt_usage: raw.ProccntUsage,
+            base_addr: raw.modBaseAddr,
+            base_size: raw.modBaseSize,
+            h_module: raw.hModule,
+            sz_module: to_u16cstring!(raw.szModule),
+            sz_exe_path: to_u16cstring!(raw.szExePath),
+            is_main: false,
+        }
+    }
+
+    fn to_raw(&self) -> Self::Raw {
+        let mut raw = Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>() as u32,
+            th32ModuleID: self.module_id,
+            th32ProcessID: self.process_id,
+            GlblcntUsage: self.glblcnt_usage,
+            ProccntUsage: self.proccnt_usage,
+            modBaseAddr: self.base_addr,
+            modBaseSize: self.base_size,
+            hModule: self.h_module,
+            szModule: [0; MAX_MODULE_NAME32 + 1],
+            szExePath: [0; MAX_PATH],
+        };
+        copy_wide(&mut raw.szModule, &self.sz_module);
+        copy_wide(&mut raw.szExePath, &self.sz_exe_path);
+        raw
+    }
+
+    #[inline]
+    fn mark_first(&mut self) {
+        self.is_main = true;
+    }
+}
+
+impl fmt::Debug for ModuleEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcessEntry")
+            .field("module_id", &self.module_id)
+            .field("process_id", &self.process_id)
+            .field("glblcnt_usage", &self.glblcnt_usage)
+            .field("proccnt_usage", &self.proccnt_usage)
+            .field("base_addr", &self.base_addr)
+            .field("base_size", &self.base_size)
+            .field("h_module", &self.h_module)
+            .field("sz_module", &self.sz_module.to_string().unwrap_or_default())
+            .field(
+                "sz_exe_file",
+                &self.sz_exe_path.to_string().unwrap_or_default(),
+            )
+            .field("is_main", &self.is_main)
+            .finish()
+    }
+}
+
+/// Borrows the module name, for comparing it against wide strings without allocating. The path
+/// is available through `sz_exe_path`.
+impl AsRef<U16CStr> for ModuleEntry {
+    fn as_ref(&self) -> &U16CStr {
+        &self.sz_module
+    }
+}
+
+/// Two module entries are equal if they belong to the same process and have the same base address
+/// and module name, the remaining fields are ignored. This allows diffing the modules of snapshots
+/// taken at different times.
+impl PartialEq for ModuleEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.process_id == other.process_id
+            && self.base_addr == other.base_addr
+            && self.sz_module == other.sz_module
+    }
+}
+
+impl Eq for ModuleEntry {}
+
+impl Hash for ModuleEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.process_id.hash(state);
+        self.base_addr_usize().hash(state);
+        self.sz_module.hash(state);
+    }
+}
+
+impl ModuleEntry {
+    /// Converts a raw `MODULEENTRY32W` obtained from your own toolhelp calls. The caller has to pass a
+    /// struct that was fully populated by a successful `*32First`/`*32Next` call.
+    pub fn from_windows(raw: MODULEENTRY32W) -> Self {
+        ModuleEntry::from_raw(raw)
+    }
+
+    /// The `th32ModuleID` of the module. This is always `1`, the field is no longer used by
+    /// Windows.
+    pub fn module_id(&self) -> u32 {
+        self.module_id
+    }
+
+    /// The load counts of the module, across all processes and in its process, as
+    /// `(global, process)`. Both are usually `0xFFFF`, which Windows reports when it doesn't
+    /// track the count.
+    pub fn usage_counts(&self) -> (u32, u32) {
+        (self.glblcnt_usage, self.proccnt_usage)
+    }
+
+    /// The base address of the module as an integer.
+    /// Like all module addresses this is an address in the target process's address space.
+    pub fn base_addr_usize(&self) -> usize {
+        self.base_addr as usize
+    }
+
+    /// The address one past the end of the module, saturating at `usize::MAX`.
+    /// Like all module addresses this is an address in the target process's address space.
+    pub fn end_addr(&self) -> usize {
+        self.base_addr_usize()
+            .saturating_add(self.base_size as usize)
+    }
+
+    /// Checks whether the given address of the target process lies within this module.
+    pub fn contains(&self, address: usize) -> bool {
+        (self.base_addr_usize()..self.end_addr()).contains(&address)
+    }
+
+    /// Returns the offset of the given address of the target process relative to the module's
+    /// base address, or [`None`] if the address doesn't lie within this module.
+    pub fn offset_of(&self, address: usize) -> Option<usize> {
+        if self.contains(address) {
+            Some(address - self.base_addr_usize())
+        } else {
+            None
+        }
+    }
+
+    /// Checks whether this module is the main executable image of its process. The OS always
+    /// lists the main image first in a module snapshot, so this holds for the first entry a
+    /// [`Snapshot`] yields, mocked or not. It is recorded while iterating, so nothing has to be
+    /// queried and this can't fail.
+    /// Entries converted with [`from_windows`](Self::from_windows) don't know their position and
+    /// are never the main module.
+    pub fn is_main_module(&self) -> bool {
+        self.is_main
+    }
+
+    /// Checks whether the module's `sz_exe_path` lies within the Windows directory or the system
+    /// directory, as reported by `GetWindowsDirectoryW` and `GetSystemDirectoryW`, which makes it
+    /// one of the operating system's modules. This helps to filter them out and focus on third
+    /// party or injected modules. The paths are compared ignoring case, see
+    /// [`wide_eq_ignore_case`], and the directories are looked up once and then cached.
+    ///
+    /// Only the location is checked, anything that was able to write to these directories can
+    /// place a module there.
+    pub fn is_system_module(&self) -> bool {
+        let path = normalize_path(self.sz_exe_path.as_slice().iter().copied());
+        system_directories()
+            .iter()
+            .any(|dir| path_is_under(&path, dir))
+    }
+
+    /// Copies the whole loaded image of the module, `base_size` bytes starting at its base
+    /// address, out of the owning process.
+    /// As `base_size` is trusted to size the buffer, images larger than
+    /// [`DEFAULT_MAX_IMAGE_SIZE`] are refused instead of attempting a huge allocation. Use
+    /// [`read_image_capped`](Self::read_image_capped) for a different limit.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the image can't be read,
+    /// which happens if any of its pages isn't readable, or with [`ErrorKind::InvalidData`] if
+    /// the module is larger than the limit.
+    pub fn read_image(&self) -> Result<Vec<u8>> {
+        self.read_image_capped(DEFAULT_MAX_IMAGE_SIZE)
+    }
+
+    /// Like [`read_image`](Self::read_image), but refuses images larger than `max_size` bytes.
+    pub fn read_image_capped(&self, max_size: usize) -> Result<Vec<u8>> {
+        let size = self.base_size as usize;
+        if size > max_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "module size of {} bytes exceeds the limit of {} bytes",
+                    size, max_size
+                ),
+            ));
+        }
+        let mut image = vec![0; size];
+        let len = read_process_memory(self.process_id, self.base_addr as _, &mut image)?;
+        image.truncate(len);
+        Ok(image)
+    }
+
+    /// Computes the SHA-256 digest of the module's file on disk at `sz_exe_path`, to check it
+    /// against databases of known good or known bad files.
+    /// This hashes the file, not the loaded image, which differs from it as soon as relocations
+    /// are applied. A file that was replaced after the module was loaded yields the digest of the
+    /// new file.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the file can't be read, with
+    /// [`ErrorKind::NotFound`] if it no longer exists or the module was never backed by a file.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let digest = module.file_sha256()?;
+    /// let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    /// ```
+    #[cfg(feature = "sha256")]
+    pub fn file_sha256(&self) -> Result<[u8; 32]> {
+        let path = PathBuf::from(self.sz_exe_path.to_os_string());
+        if path.as_os_str().is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "the module has no file path",
+            ));
+        }
+        let mut file = File::open(path)?;
+        let mut hasher = sha256::Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => return Ok(hasher.finish()),
+                Ok(len) => hasher.update(&buf[..len]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads the PE headers of the loaded module to classify it: whether it is a DLL or an
+    /// executable, the machine it was built for and whether it is a managed .NET assembly.
+    /// `reader` has to be opened for the module's process.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the headers can't be read, or
+    /// with [`ErrorKind::InvalidData`] if they aren't valid PE headers.
+    pub fn pe_info(&self, reader: &ProcessReader) -> Result<PeInfo> {
+        let header = pe::read_headers(reader, self.base_addr_usize())?;
+        Ok(pe::Headers::parse(&header)?.info())
+    }
+
+    /// Reads the section headers of the loaded module, which locate its `.text`, `.data` and other
+    /// sections in the owning process for targeted scanning. `reader` has to be opened for the
+    /// module's process.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the headers can't be read, or
+    /// with [`ErrorKind::InvalidData`] if they aren't valid PE headers, which is the case for
+    /// images that stripped or overwrote their headers after loading, or if a section lies
+    /// outside of the module.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let reader = tlhelp32::ProcessReader::open(module.process_id)?;
+    /// for section in module.sections(&reader)? {
+    ///     if section.is_executable() {
+    ///         println!("{} at {:#x}", section.name, section.address);
+    ///     }
+    /// }
+    /// ```
+    pub fn sections(&self, reader: &ProcessReader) -> Result<Vec<PeSection>> {
+        let header = pe::read_headers(reader, self.base_addr_usize())?;
+        pe::Headers::parse(&header)?.sections(self.base_addr_usize(), self.base_size as usize)
+    }
+
+    /// Resolves the export `name` of this module to its absolute address in the owning process by
+    /// walking the export directory of the loaded image. `reader` has to be opened for the
+    /// module's process.
+    /// Returns [`None`] if the module doesn't export `name`. Exports that are forwarded to another
+    /// module, like many of `kernel32.dll`'s to `kernelbase.dll`, aren't followed and also yield
+    /// [`None`]; resolve them in the target module instead.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the image can't be read, or
+    /// with [`ErrorKind::InvalidData`] if its headers or export directory are malformed.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let reader = tlhelp32::ProcessReader::open(module.process_id)?;
+    /// let load_library = module.export(&reader, "LoadLibraryW")?;
+    /// ```
+    pub fn export(&self, reader: &ProcessReader, name: &str) -> Result<Option<usize>> {
+        pe::find_export(reader, self.base_addr_usize(), name)
+    }
+
+    /// Forcibly unloads the module from its process by running `FreeLibrary(h_module)` on a
+    /// remote thread in it, waiting for the thread to finish and returning its exit code, which
+    /// is the return value of `FreeLibrary`: nonzero on success.
+    ///
+    /// **This is dangerous.** Code of the target process that still uses the module will crash it,
+    /// and as `FreeLibrary` only decrements the module's reference count it might need multiple
+    /// calls to actually unload it. Only same-bitness processes are supported, since the address of
+    /// `FreeLibrary` is resolved in the calling process.
+    /// This requires the `PROCESS_CREATE_THREAD`, `PROCESS_QUERY_INFORMATION`,
+    /// `PROCESS_VM_OPERATION`, `PROCESS_VM_WRITE` and `PROCESS_VM_READ` access rights.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to create the remote thread, or with [`ErrorKind::Unsupported`] if the process has a
+    /// different bitness than the calling one.
+    pub fn force_unload(&self) -> Result<u32> {
+        let process = open_process(
+            PROCESS_CREATE_THREAD
+                | PROCESS_QUERY_INFORMATION
+                | PROCESS_VM_OPERATION
+                | PROCESS_VM_WRITE
+                | PROCESS_VM_READ,
+            self.process_id,
+        )?;
+        if is_wow64(raw(&process))? != is_wow64(unsafe { GetCurrentProcess() })? {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "cannot unload modules of a process with a different bitness",
+            ));
+        }
+        unsafe {
+            // kernel32 is mapped at the same address in every process of the same bitness
+            let kernel32 = GetModuleHandleA(b"kernel32.dll\0".as_ptr() as *const _);
+            let free_library = GetProcAddress(kernel32, b"FreeLibrary\0".as_ptr() as *const _);
+            if free_library.is_null() {
+                return Err(Error::last_os_error());
+            }
+            let thread = CreateRemoteThread(
+                raw(&process),
+                ptr::null_mut(),
+                0,
+                mem::transmute::<FARPROC, LPTHREAD_START_ROUTINE>(free_library),
+                self.h_module as *mut _,
+                0,
+                ptr::null_mut(),
+            );
+            if thread.is_null() {
+                return Err(Error::last_os_error());
+            }
+            let thread = OwnedHandle::from_raw_handle(thread as _);
+            if WaitForSingleObject(raw(&thread), INFINITE) == WAIT_FAILED {
+                return Err(Error::last_os_error());
+            }
+            let mut exit_code = 0;
+            if GetExitCodeThread(raw(&thread), &mut exit_code) == 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(exit_code)
+        }
+    }
+}
+
+/// A heap list taken from a [`Snapshot`]. This struct is an iterator over the heap entries of its heap.
+/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagheaplist32)
+#[allow(missing_docs, missing_copy_implementations)]
+pub struct HeapList {
+    pub process_id: u32,
+    pub heap_id: usize,
+    pub flags: u32,
+    current: Option<HEAPENTRY32>,
+}
+
+impl TagTl32 for HeapList {
+    type Raw = HEAPLIST32;
+    const FLAGS: u32 = TH32CS_SNAPHEAPLIST;
+    const ITER_FIRST: Tl32helpFunc<Self::Raw> = Heap32ListFirst;
+    const ITER_NEXT: Tl32helpFunc<Self::Raw> = Heap32ListNext;
+
+    #[inline]
+    fn init_raw() -> Self::Raw {
+        Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>(),
+            ..unsafe { mem::uninitialized() }
+        }
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw) -> Self {
+        let mut entry = HEAPENTRY32 {
+            dwSize: mem::size_of::<HEAPENTRY32>(),
+            ..unsafe { mem::uninitialized() }
+        };
+        let current = if unsafe { Heap32First(&mut entry, raw.th32ProcessID, raw.th32HeapID) == 0 }
+        {
+            None
+        } else {
+            Some(entry)
+        };
+        HeapList {
+            process_id: raw.th32ProcessID,
+            heap_id: raw.th32HeapID,
+            flags: raw.dwFlags,
+            current,
+        }
+    }
+
+    fn to_raw(&self) -> Self::Raw {
+        Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>(),
+            th32ProcessID: self.process_id,
+            th32HeapID: self.heap_id,
+            dwFlags: self.flags,
+        }
+    }
+}
+
+/// The clone continues from the same heap entry as the original, the two then iterate
+/// independently of each other. The position in the heap is held by the embedded `HEAPENTRY32`
+/// cursor alone, which `Heap32Next` advances, so the clone gets a copy of it rather than sharing
+/// it. Use [`HeapList::restarted`] for a heap list starting over at the first entry instead.
+///
+/// [`Snapshot`] itself isn't [`Clone`], as all iterations of a toolhelp snapshot handle share
+/// its cursor, but the heap lists it yields can be cloned freely.
+impl Clone for HeapList {
+    fn clone(&self) -> Self {
+        HeapList {
+            process_id: self.process_id,
+            heap_id: self.heap_id,
+            flags: self.flags,
+            current: self.current,
+        }
+    }
+}
+
+impl HeapList {
+    /// Returns a heap list over the same heap that starts over at its first entry, regardless of
+    /// how far this one has been iterated. Like heap lists taken from a [`Snapshot`], this
+    /// fetches the first entry right away, so the heap is walked as it is now.
+    pub fn restarted(&self) -> Self {
+        HeapList::from_raw(HEAPLIST32 {
+            dwSize: mem::size_of::<HEAPLIST32>(),
+            th32ProcessID: self.process_id,
+            th32HeapID: self.heap_id,
+            dwFlags: self.flags,
+        })
+    }
+
+    /// Converts a raw `HEAPLIST32` obtained from your own toolhelp calls. The caller has to pass a
+    /// struct that was fully populated by a successful `*32First`/`*32Next` call.
+    /// Like heap lists taken from a [`Snapshot`], this fetches the first entry of the heap.
+    pub fn from_windows(raw: HEAPLIST32) -> Self {
+        HeapList::from_raw(raw)
+    }
+
+    /// Iterates the heap entries by reference, so the heap list and its `heap_id` and `flags`
+    /// remain accessible after walking it. Entries taken this way are consumed, continuing to
+    /// iterate afterwards picks up where the borrow stopped.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for mut heap_list in tlhelp32::Snapshot::new_heap_list(pid)? {
+    ///     let size: usize = heap_list.iter().map(|entry| entry.block_size).sum();
+    ///     println!("heap {:#x}: {} bytes", heap_list.heap_id, size);
+    /// }
+    /// ```
+    pub fn iter(&mut self) -> impl Iterator<Item = HeapEntry> + '_ {
+        self.by_ref()
+    }
+
+    /// Collects the remaining heap entries into any [`FromIterator`] collection in one call.
+    /// A heap list holds no handle of its own, this exists for symmetry with
+    /// [`Snapshot::collect_into`].
+    pub fn collect_into<C: FromIterator<HeapEntry>>(self) -> C {
+        self.collect()
+    }
+}
+
+impl Iterator for HeapList {
+    type Item = HeapEntry;
+    fn next(&mut self) -> Option<Self::Item> {
+        let val = HeapEntry::from_raw(self.current?);
+        if unsafe { Heap32Next(self.current.as_mut().unwrap()) == 0 } {
+            self.current = None
+        }
+        Some(val)
+    }
+}
+
+impl fmt::Debug for HeapList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeapList")
+            .field("process_id", &self.process_id)
+            .field("heap_id", &self.heap_id)
+            .field("flags", &self.flags)
+            .field("exhausted", &self.current.is_none())
+            .finish()
+    }
+}
+
+/// A heap entry taken from a [`HeapList`].
+/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagheapentry32)
+#[allow(missing_docs)]
+#[derive(Clone, Copy)]
+pub struct HeapEntry {
+    pub handle: HANDLE,
+    pub address: usize,
+    pub block_size: usize,
+    pub flags: u32,
+    pub process_id: u32,
+    pub heap_id: usize,
+}
+
+impl HeapEntry {
+    /// Converts a raw `HEAPENTRY32` obtained from your own toolhelp calls. The caller has to pass a
+    /// struct that was fully populated by a successful `Heap32First`/`Heap32Next` call.
+    pub fn from_windows(raw: HEAPENTRY32) -> Self {
+        HeapEntry::from_raw(raw)
+    }
+
+    /// Checks whether the given address lies within this block, `[address, address + block_size)`.
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.address && address - self.address < self.block_size
+    }
+
+    fn from_raw(raw: HEAPENTRY32) -> Self {
+        HeapEntry {
+            handle: raw.hHandle,
+            address: raw.dwAddress,
+            block_size: raw.dwBlockSize,
+            flags: raw.dwFlags,
+            process_id: raw.th32ProcessID,
+            heap_id: raw.th32HeapID,
+        }
+    }
+}
+
+impl fmt::Debug for HeapEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeapEntry")
+            .field("handle", &format_args!("{:#x}", self.handle as usize))
+            .field("address", &format_args!("{:#x}", self.address))
+            .field("block_size", &self.block_size)
+            .field("flags", &self.flags)
+            .field("process_id", &self.process_id)
+            .field("heap_id", &format_args!("{:#x}", self.heap_id))
+            .finish()
+    }
+}
+
+/// A thread entry taken from a [`Snapshot`].
+/// For more information on the fields meanings visit the [`microsoft docs`](https://docs.microsoft.com/en-us/windows/desktop/api/tlhelp32/ns-tlhelp32-tagthreadentry32)
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadEntry {
+    pub thread_id: u32,
+    pub owner_process_id: u32,
+    pub base_pri: i32,
+}
+
+impl TagTl32 for ThreadEntry {
+    type Raw = THREADENTRY32;
+    const FLAGS: u32 = TH32CS_SNAPTHREAD;
+    const ITER_FIRST: Tl32helpFunc<Self::Raw> = Thread32First;
+    const ITER_NEXT: Tl32helpFunc<Self::Raw> = Thread32Next;
+
+    #[inline]
+    fn init_raw() -> Self::Raw {
+        Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>() as u32,
+            ..unsafe { mem::uninitialized() }
+        }
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw) -> Self {
+        ThreadEntry {
+            thread_id: raw.th32ThreadID,
+            owner_process_id: raw.th32OwnerProcessID,
+            base_pri: raw.tpBasePri,
+        }
+    }
+
+    fn to_raw(&self) -> Self::Raw {
+        Self::Raw {
+            dwSize: mem::size_of::<Self::Raw>() as u32,
+            cntUsage: 0,
+            th32ThreadID: self.thread_id,
+            th32OwnerProcessID: self.owner_process_id,
+            tpBasePri: self.base_pri,
+            tpDeltaPri: 0,
+            dwFlags: 0,
+        }
+    }
+}
+
+impl ThreadEntry {
+    /// Converts a raw `THREADENTRY32` obtained from your own toolhelp calls. The caller has to pass a
+    /// struct that was fully populated by a successful `*32First`/`*32Next` call.
+    pub fn from_windows(raw: THREADENTRY32) -> Self {
+        ThreadEntry::from_raw(raw)
+    }
+
+    /// Retrieves the address the thread started executing at, as passed to `CreateThread`. This
+    /// helps to identify what spawned the thread, and a start address outside of any loaded
+    /// module hints at injected code.
+    ///
+    /// This opens the thread with the `THREAD_QUERY_INFORMATION` access right, the limited
+    /// query right isn't sufficient. For threads of WOW64 processes the returned address is
+    /// that of the 64-bit thread start routine if the calling process is 64-bit.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the thread
+    /// or to query its start address
+    pub fn start_address(&self) -> Result<usize> {
+        let thread = open_thread(THREAD_QUERY_INFORMATION, self.thread_id)?;
+        let mut address = 0usize;
+        unsafe {
+            ntdll::query_thread(
+                raw(&thread),
+                ntdll::THREAD_QUERY_SET_WIN32_START_ADDRESS,
+                &mut address,
+            )?
+        };
+        Ok(address)
+    }
+
+    /// Queries the creation time and the CPU time the thread has used so far in kernel and user
+    /// mode. Sampling these repeatedly and dividing the growth of the CPU times by the elapsed
+    /// time yields the CPU usage of the thread.
+    ///
+    /// Windows reports the times in 100 nanosecond units, which convert to [`Duration`]s and
+    /// [`SystemTime`]s without loss. The CPU times are only updated on every clock tick, about
+    /// every 15.6 milliseconds by default, though, so they are much less precise than their unit
+    /// suggests.
+    /// This opens the thread with the `THREAD_QUERY_LIMITED_INFORMATION` access right.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the thread
+    /// or to query its times, access is denied for threads of protected processes.
+    pub fn times(&self) -> Result<ThreadTimes> {
+        let thread = open_thread(THREAD_QUERY_LIMITED_INFORMATION, self.thread_id)?;
+        let mut times: [FILETIME; 4] = unsafe { mem::zeroed() };
+        let [creation, exit, kernel, user] = &mut times;
+        if unsafe { GetThreadTimes(raw(&thread), creation, exit, kernel, user) } == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(ThreadTimes {
+            creation_time: system_time(times[0]),
+            kernel_time: duration(times[2]),
+            user_time: duration(times[3]),
+        })
+    }
+
+    /// Captures the registers of the thread by briefly suspending it, calling `GetThreadContext`
+    /// and resuming it again. Nothing prevents other code from resuming the thread in between,
+    /// in which case the captured registers might be torn.
+    ///
+    /// If the calling process is 64-bit, the context of a thread of a WOW64 process is that of
+    /// its 64-bit part, usually stopped inside the WOW64 layer rather than in the 32-bit code.
+    /// This opens the thread with the `THREAD_GET_CONTEXT` and `THREAD_SUSPEND_RESUME` access
+    /// rights.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open, suspend
+    /// or query the thread, or with [`ErrorKind::InvalidInput`] for the calling thread, which
+    /// can't suspend itself.
+    pub fn get_context(&self) -> Result<ThreadContext> {
+        if self.thread_id == unsafe { GetCurrentThreadId() } {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "cannot capture the context of the calling thread",
+            ));
+        }
+        let thread = open_thread(THREAD_GET_CONTEXT | THREAD_SUSPEND_RESUME, self.thread_id)?;
+        if unsafe { SuspendThread(raw(&thread)) } == u32::MAX {
+            return Err(Error::last_os_error());
+        }
+        let mut context = ThreadContext {
+            raw: unsafe { mem::zeroed() },
+        };
+        context.raw.ContextFlags = CONTEXT_FULL;
+        let res = unsafe { GetThreadContext(raw(&thread), &mut context.raw) };
+        // fetch the error before resuming overwrites it
+        let err = Error::last_os_error();
+        unsafe { ResumeThread(raw(&thread)) };
+        if res == 0 {
+            Err(err)
+        } else {
+            Ok(context)
+        }
+    }
+}
+
+/// The times of a thread as queried by [`ThreadEntry::times`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ThreadTimes {
+    /// The time the thread was created at.
+    pub creation_time: SystemTime,
+    /// The time the thread spent executing in kernel mode.
+    pub kernel_time: Duration,
+    /// The time the thread spent executing in user mode.
+    pub user_time: Duration,
+}
+
+/// The registers of a thread as captured by [`ThreadEntry::get_context`].
+/// This wraps the architecture specific `CONTEXT` with the alignment `GetThreadContext` requires.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct ThreadContext {
+    raw: CONTEXT,
+}
+
+impl ThreadContext {
+    /// The instruction pointer, `Rip`, `Eip` or `Pc` depending on the architecture.
+    pub fn instruction_pointer(&self) -> usize {
+        #[cfg(target_arch = "x86_64")]
+        return self.raw.Rip as usize;
+        #[cfg(target_arch = "x86")]
+        return self.raw.Eip as usize;
+        #[cfg(target_arch = "aarch64")]
+        return self.raw.Pc as usize;
+    }
+
+    /// The stack pointer, `Rsp`, `Esp` or `Sp` depending on the architecture.
+    pub fn stack_pointer(&self) -> usize {
+        #[cfg(target_arch = "x86_64")]
+        return self.raw.Rsp as usize;
+        #[cfg(target_arch = "x86")]
+        return self.raw.Esp as usize;
+        #[cfg(target_arch = "aarch64")]
+        return self.raw.Sp as usize;
+    }
+
+    /// The raw `CONTEXT`, for access to the remaining registers.
+    pub fn as_raw(&self) -> &CONTEXT {
+        &self.raw
+    }
+}
+
+impl fmt::Debug for ThreadContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadContext")
+            .field(
+                "instruction_pointer",
+                &format_args!("{:#x}", self.instruction_pointer()),
+            )
+            .field(
+                "stack_pointer",
+                &format_args!("{:#x}", self.stack_pointer()),
+            )
+            .finish()
+    }
+}
+
+/// The `TH32CS_*` flags a [`Snapshot`] was created with, as returned by
+/// [`Snapshot::snapshot_flags`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SnapshotFlags(u32);
+
+impl SnapshotFlags {
+    /// `TH32CS_SNAPHEAPLIST`
+    pub const HEAPLIST: Self = SnapshotFlags(TH32CS_SNAPHEAPLIST);
+    /// `TH32CS_SNAPPROCESS`
+    pub const PROCESS: Self = SnapshotFlags(TH32CS_SNAPPROCESS);
+    /// `TH32CS_SNAPTHREAD`
+    pub const THREAD: Self = SnapshotFlags(TH32CS_SNAPTHREAD);
+    /// `TH32CS_SNAPMODULE`
+    pub const MODULE: Self = SnapshotFlags(TH32CS_SNAPMODULE);
+    /// `TH32CS_SNAPMODULE32`
+    pub const MODULE32: Self = SnapshotFlags(TH32CS_SNAPMODULE32);
+    /// `TH32CS_INHERIT`
+    pub const INHERIT: Self = SnapshotFlags(TH32CS_INHERIT);
+
+    const NAMES: [(Self, &'static str); 6] = [
+        (Self::HEAPLIST, "HEAPLIST"),
+        (Self::PROCESS, "PROCESS"),
+        (Self::THREAD, "THREAD"),
+        (Self::MODULE, "MODULE"),
+        (Self::MODULE32, "MODULE32"),
+        (Self::INHERIT, "INHERIT"),
+    ];
+
+    /// The raw flags.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if all flags of `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl From<u32> for SnapshotFlags {
+    fn from(bits: u32) -> Self {
+        SnapshotFlags(bits)
+    }
+}
+
+impl std::ops::BitOr for SnapshotFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        SnapshotFlags(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Debug for SnapshotFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name);
+        f.write_str("SnapshotFlags(")?;
+        if let Some(first) = names.next() {
+            f.write_str(first)?;
+            for name in names {
+                write!(f, " | {}", name)?;
+            }
+        }
+        let unknown = Self::NAMES
+            .iter()
+            .fold(self.0, |bits, (flag, _)| bits & !flag.0);
+        if unknown != 0 {
+            write!(
+                f,
+                "{}{:#x}",
+                if unknown == self.0 { "" } else { " | " },
+                unknown
+            )?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// The inner error of the [`io::Error`] creating a [`Snapshot`] or [`CombinedSnapshot`] fails
+/// with, which records what was being snapshotted in addition to the underlying error.
+/// The [`io::Error`] has the [`kind`](Error::kind) of the underlying error, but no
+/// [`raw_os_error`](Error::raw_os_error) of its own, the os error code is that of the `source`
+/// field.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// if let Err(e) = tlhelp32::Snapshot::new_module(pid) {
+///     if let Some(e) = e.get_ref().and_then(|e| e.downcast_ref::<tlhelp32::SnapshotError>()) {
+///         println!("pid {} failed with {:?}", e.pid, e.source.raw_os_error());
+///     }
+/// }
+/// ```
+///
+/// [`io::Error`]: std::io::Error
+#[derive(Debug)]
+pub struct SnapshotError {
+    /// The process id the snapshot was requested for, as passed to `CreateToolhelp32Snapshot`.
+    pub pid: u32,
+    /// The `TH32CS_*` flags the snapshot was requested with.
+    pub flags: u32,
+    /// The underlying error.
+    pub source: Error,
+}
+
+impl SnapshotError {
+    fn new(pid: u32, flags: u32, source: Error) -> Self {
+        SnapshotError { pid, flags, source }
+    }
+
+    fn last_os_error(pid: u32, flags: u32) -> Self {
+        Self::new(pid, flags, Error::last_os_error())
+    }
+
+    /// The kind of the underlying error.
+    pub fn kind(&self) -> ErrorKind {
+        self.source.kind()
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let types = [
+            (TH32CS_SNAPPROCESS, "process"),
+            (TH32CS_SNAPTHREAD, "thread"),
+            (TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, "module"),
+            (TH32CS_SNAPHEAPLIST, "heap list"),
+        ];
+        let mut included = types.iter().filter(|(flags, _)| self.flags & flags != 0);
+        let name = match (included.next(), included.next()) {
+            (Some((_, name)), None) => name,
+            (None, _) => "toolhelp",
+            (Some(_), Some(_)) => "combined",
+        };
+        write!(f, "failed to create a {} snapshot", name)?;
+        // process and thread snapshots ignore the process id
+        if self.flags & (TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32 | TH32CS_SNAPHEAPLIST) != 0 {
+            write!(f, " of process {}", self.pid)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<SnapshotError> for Error {
+    fn from(e: SnapshotError) -> Self {
+        Error::new(e.kind(), e)
+    }
+}
+
+/// The functions a [`Snapshot`] walks its entries with. Toolhelp snapshots call the `*32First`
+/// and `*32Next` functions of `T` on their handle, mocked snapshots replay fixed entries, and
+/// the iteration code is the same for both.
+trait EntrySource<T: TagTl32> {
+    /// Fills in the first entry like `*32First`, or sets the last error and returns `false`.
+    fn first(&mut self, raw: &mut T::Raw) -> bool;
+    /// Fills in the entry after `raw` like `*32Next`, or sets the last error and returns `false`.
+    fn next(&mut self, raw: &mut T::Raw) -> bool;
+    /// Converts the raw entry the walk is currently at.
+    fn convert(&mut self, raw: T::Raw) -> T;
+}
+
+impl<T: TagTl32> EntrySource<T> for HANDLE {
+    #[inline]
+    fn first(&mut self, raw: &mut T::Raw) -> bool {
+        unsafe { T::ITER_FIRST(*self, raw) != 0 }
+    }
+
+    #[inline]
+    fn next(&mut self, raw: &mut T::Raw) -> bool {
+        unsafe { T::ITER_NEXT(*self, raw) != 0 }
+    }
+
+    #[inline]
+    fn convert(&mut self, raw: T::Raw) -> T {
+        T::from_raw(raw)
+    }
+}
+
+/// Replays fixed entries, see [`Snapshot::mock`].
+/// The entries are kept alongside their raw counterparts and handed out as they are, as
+/// converting the raw entries back could query the OS, like [`HeapList`] does for its first
+/// heap entry.
+struct MockSource<T: TagTl32> {
+    raws: Vec<T::Raw>,
+    entries: Vec<Option<T>>,
+    /// The index of the raw entry the walk is at.
+    position: usize,
+}
+
+impl<T: TagTl32> MockSource<T> {
+    fn read(&mut self, position: usize, raw: &mut T::Raw) -> bool {
+        self.position = position;
+        match self.raws.get(position) {
+            Some(entry) => {
+                *raw = *entry;
+                true
+            }
+            None => {
+                unsafe { SetLastError(ERROR_NO_MORE_FILES) };
+                false
+            }
+        }
+    }
+}
+
+impl<T: TagTl32> fmt::Debug for MockSource<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockSource")
+            .field("len", &self.raws.len())
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl<T: TagTl32> EntrySource<T> for MockSource<T> {
+    fn first(&mut self, raw: &mut T::Raw) -> bool {
+        self.read(0, raw)
+    }
+
+    fn next(&mut self, raw: &mut T::Raw) -> bool {
+        self.read(self.position + 1, raw)
+    }
+
+    fn convert(&mut self, raw: T::Raw) -> T {
+        // every entry is converted at most once, as the walk moves on right after
+        self.entries[self.position]
+            .take()
+            .unwrap_or_else(|| T::from_raw(raw))
+    }
+}
+
+/// Where a [`Snapshot`] takes its entries from.
+#[derive(Debug)]
+enum Source<T: TagTl32> {
+    /// Walks the toolhelp snapshot behind the handle, which the [`Snapshot`] owns.
+    Handle(HANDLE),
+    /// Yields fixed entries, see [`Snapshot::mock`].
+    Mock(MockSource<T>),
+}
+
+impl<T: TagTl32> Source<T> {
+    /// The snapshot handle, or a null handle for mocks.
+    fn handle(&self) -> HANDLE {
+        match self {
+            Source::Handle(snapshot) => *snapshot,
+            Source::Mock(_) => ptr::null_mut(),
+        }
+    }
+
+    #[inline]
+    fn entries(&mut self) -> &mut dyn EntrySource<T> {
+        match self {
+            Source::Handle(snapshot) => snapshot,
+            Source::Mock(mock) => mock,
+        }
+    }
+}
+
+/// An iterator for the Toolhelp32Snapshot Windows API.
+/// You create them by calling the appropriate `new_*` methods.
+#[derive(Debug)]
+pub struct Snapshot<T: TagTl32> {
+    source: Source<T>,
+    current: Option<T::Raw>,
+    first_pending: bool,
+    /// Whether `current` is the first entry of the snapshot.
+    at_first: bool,
+    process_id: u32,
+    flags: SnapshotFlags,
+    /// The error code the walk last stopped with, if it wasn't the end of the entries.
+    error: Option<u32>,
+}
+
+impl<T: TagTl32> Snapshot<T> {
+    #[inline]
+    fn new(pid: u32) -> std::result::Result<Self, SnapshotError> {
+        Self::with_flags(T::FLAGS, pid)
+    }
+
+    fn with_flags(flags: u32, pid: u32) -> std::result::Result<Self, SnapshotError> {
+        let pid = Self::resolve_pid(pid);
+        match unsafe { CreateToolhelp32Snapshot(flags, pid) } {
+            INVALID_HANDLE_VALUE => Err(SnapshotError::last_os_error(pid, flags)),
+            snapshot => Ok(unsafe { Self::from_valid_handle(snapshot, pid, flags) }),
+        }
+    }
+
+    /// Module and heap snapshots interpret [`CURRENT_PROCESS`] as the calling process, so it gets
+    /// resolved to the actual id for the entries to report it instead of `0`.
+    fn resolve_pid(pid: u32) -> u32 {
+        if pid == CURRENT_PROCESS && T::FLAGS & (TH32CS_SNAPMODULE | TH32CS_SNAPHEAPLIST) != 0 {
+            unsafe { GetCurrentProcessId() }
+        } else {
+            pid
+        }
+    }
+
+    /// Creates a snapshot from a given handle. Avoid using this unless you have a specific reason to.
+    /// # Safety
+    /// This function does not check whether the generic type and the flags belong together.
+    /// If used incorrectly this will produce an iterator that returns [`None`] from the very beginning.
+    pub unsafe fn from_handle(snapshot: HANDLE) -> Result<Self> {
+        match snapshot {
+            INVALID_HANDLE_VALUE => Err(Error::last_os_error()),
+            snapshot => Ok(Self::from_valid_handle(snapshot, 0, T::FLAGS)),
+        }
+    }
+
+    /// Creates a new [`Snapshot`] for the given process id without going through [`io::Error`],
+    /// for callers that want to avoid its allocation and formatting machinery. The process id is
+    /// ignored for process and thread snapshots.
+    ///
+    /// [`io::Error`]: std::io::Error
+    /// # Errors
+    /// This function fails and returns the raw `GetLastError` code if it is unable to create a
+    /// [`Snapshot`]
+    pub fn new_raw_err(pid: u32) -> std::result::Result<Self, u32> {
+        let pid = Self::resolve_pid(pid);
+        match unsafe { CreateToolhelp32Snapshot(T::FLAGS, pid) } {
+            INVALID_HANDLE_VALUE => Err(unsafe { GetLastError() }),
+            snapshot => Ok(unsafe { Self::from_valid_handle(snapshot, pid, T::FLAGS) }),
+        }
+    }
+
+    /// Like [`from_handle`](Self::from_handle), but the first entry is only fetched once the
+    /// snapshot is iterated. This avoids any iteration work for snapshots that are only created
+    /// to pass their [`handle`](Self::handle) elsewhere.
+    /// # Safety
+    /// See [`from_handle`](Self::from_handle).
+    pub unsafe fn from_handle_lazy(snapshot: HANDLE) -> Result<Self> {
+        match snapshot {
+            INVALID_HANDLE_VALUE => Err(Error::last_os_error()),
+            snapshot => Ok(Snapshot {
+                source: Source::Handle(snapshot),
+                current: None,
+                first_pending: true,
+                at_first: false,
+                process_id: 0,
+                flags: SnapshotFlags(T::FLAGS),
+                error: None,
+            }),
+        }
+    }
+
+    unsafe fn from_valid_handle(snapshot: HANDLE, process_id: u32, flags: u32) -> Self {
+        let mut this = Snapshot {
+            source: Source::Handle(snapshot),
+            current: None,
+            first_pending: true,
+            at_first: false,
+            process_id,
+            flags: SnapshotFlags(flags),
+            error: None,
+        };
+        this.fetch_first();
+        this
+    }
+
+    /// Fetches the first entry if that hasn't happened yet.
+    #[inline]
+    fn fetch_first(&mut self) {
+        if self.first_pending {
+            self.first_pending = false;
+            let mut entry = T::init_raw();
+            if self.source.entries().first(&mut entry) {
+                self.current = Some(entry);
+                self.at_first = true;
+            } else {
+                self.record_error();
+            }
+        }
+    }
+
+    /// Advances the cursor to the next entry, returning `false` once there is none.
+    fn advance(&mut self) -> bool {
+        let current = match self.current.as_mut() {
+            Some(current) => current,
+            None => return false,
+        };
+        self.at_first = false;
+        if !self.source.entries().next(current) {
+            self.current = None;
+            self.record_error();
+            return false;
+        }
+        true
+    }
+
+    /// Returns the current raw entry and advances the cursor to the next one.
+    fn next_raw_entry(&mut self) -> Option<T::Raw> {
+        self.fetch_first();
+        let raw = self.current?;
+        self.advance();
+        Some(raw)
+    }
+
+    /// Yields the remaining entries as the raw structs toolhelp fills in, like `PROCESSENTRY32W`
+    /// or `MODULEENTRY32W`, without converting them. This is an escape hatch for reading the
+    /// fields the converted entries leave out, and it avoids the allocations of the conversion.
+    /// The raw structs are `Copy` and defined by `winapi`, so their layout and field names are
+    /// those of the Windows SDK rather than of this crate.
+    /// A [`mock`](Self::mock) snapshot yields the raw counterparts of its entries.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for raw in tlhelp32::Snapshot::new_process()?.raw_entries() {
+    ///     println!("{} {:#x}", raw.th32ProcessID, raw.dwFlags);
+    /// }
+    /// ```
+    pub fn raw_entries(mut self) -> impl Iterator<Item = T::Raw> {
+        iter::from_fn(move || self.next_raw_entry())
+    }
+
+    /// Remembers why the last `*32First` or `*32Next` call failed, unless it was because there
+    /// are no more entries.
+    fn record_error(&mut self) {
+        match unsafe { GetLastError() } {
+            ERROR_NO_MORE_FILES => (),
+            code => self.error = Some(code),
+        }
+    }
+
+    /// Like [`Iterator::next`], but tells a walk that ended early because the `*32First` or
+    /// `*32Next` function failed apart from one that reached the end of the entries. Module walks
+    /// for example can fail with `ERROR_BAD_LENGTH` midway when the modules of the process change
+    /// while the snapshot is taken.
+    /// [`Iterator::next`] remains the convenient option, it simply ends the iteration on such
+    /// failures. The failure is kept though, so `try_next` reports it even when called after the
+    /// iteration ended, once.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the walk ended for any other
+    /// reason than `ERROR_NO_MORE_FILES`
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let mut snapshot = tlhelp32::Snapshot::new_module(pid)?;
+    /// while let Some(module) = snapshot.try_next()? {
+    ///     println!("{:?}", module);
+    /// }
+    /// ```
+    pub fn try_next(&mut self) -> Result<Option<T>> {
+        match self.next() {
+            Some(entry) => Ok(Some(entry)),
+            None => match self.error.take() {
+                Some(code) => Err(Error::from_raw_os_error(code as i32)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// Creates a snapshot that yields the given entries instead of querying the OS.
+    /// This lets code consuming snapshots be tested deterministically against a fixed set of
+    /// entries. The entries are walked like those of a toolhelp snapshot, so a mocked module
+    /// snapshot marks its first entry as the [main module](ModuleEntry::is_main_module), and
+    /// [`raw_entries`](Self::raw_entries) yields the raw structs toolhelp would have filled in.
+    /// Only the handle differs: the snapshot has none, so [`handle`](Self::handle) returns a null
+    /// handle and [`close`](Self::close) always succeeds.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let snapshot = tlhelp32::Snapshot::mock(vec![thread_a, thread_b]);
+    /// assert_eq!(snapshot.count(), 2);
+    /// ```
+    pub fn mock(entries: Vec<T>) -> Self {
+        let raws = entries.iter().map(T::to_raw).collect();
+        let entries = entries.into_iter().map(Some).collect();
+        Snapshot {
+            source: Source::Mock(MockSource {
+                raws,
+                entries,
+                position: 0,
+            }),
+            current: None,
+            first_pending: true,
+            at_first: false,
+            process_id: 0,
+            flags: SnapshotFlags(0),
+            error: None,
+        }
+    }
+
+    /// Creates a snapshot that takes over the given handle, closing it once the snapshot is dropped.
+    /// If the handle wasn't created with the flags belonging to `T` the resulting iterator is
+    /// empty from the very beginning.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the handle is invalid
+    pub fn from_owned_handle(handle: OwnedHandle) -> Result<Self> {
+        unsafe { Self::from_handle(handle.into_raw_handle() as HANDLE) }
+    }
+
+    /// Converts the snapshot into a [`Stream`](futures::Stream) of its entries.
+    /// Toolhelp iteration itself is blocking, but walking a snapshot is cheap as it only copies
+    /// out of the already captured data, so every poll simply yields the next entry. Like the
+    /// snapshot itself the stream is not [`Send`].
+    #[cfg(feature = "futures")]
+    pub fn into_stream(self) -> impl futures::Stream<Item = T> {
+        futures::stream::iter(self)
+    }
+
+    /// Retrieves the windows snapshot handle
+    pub fn handle(&self) -> HANDLE {
+        self.source.handle()
+    }
+
+    /// The id of the process this snapshot was created for, with [`CURRENT_PROCESS`] resolved to
+    /// the id of the calling process. This is `0` for the system-wide process and thread snapshots
+    /// as well as for snapshots created from a handle or mocked.
+    pub fn process_id(&self) -> u32 {
+        self.process_id
+    }
+
+    /// The `TH32CS_*` flags the snapshot was created with. For snapshots created from a handle
+    /// these are assumed to be the default flags of `T`, mocked snapshots report `0`.
+    pub fn flags(&self) -> u32 {
+        self.flags.bits()
+    }
+
+    /// Like [`flags`](Self::flags), but typed as [`SnapshotFlags`].
+    pub fn snapshot_flags(&self) -> SnapshotFlags {
+        self.flags
+    }
+
+    /// Consumes the snapshot and returns its handle without closing it.
+    /// The caller becomes responsible for closing the handle with `CloseHandle`, otherwise it
+    /// is leaked. To hand ownership back to a [`Snapshot`] use
+    /// [`from_owned_handle`](Self::from_owned_handle).
+    pub fn into_raw_handle(self) -> HANDLE {
+        self.into_source().handle()
+    }
+
+    /// Takes the source out of the snapshot without closing its handle.
+    fn into_source(self) -> Source<T> {
+        let this = ManuallyDrop::new(self);
+        // the remaining fields are all `Copy`, so nothing else needs to be dropped
+        unsafe { ptr::read(&this.source) }
+    }
+
+    /// Consumes the snapshot and closes its handle, returning the os error if that fails.
+    /// Closing only fails if the handle was invalid to begin with, so relying on the snapshot
+    /// being closed when dropped is fine for most callers.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if `CloseHandle` fails
+    pub fn close(self) -> Result<()> {
+        let handle = match self.into_source() {
+            Source::Handle(handle) => handle,
+            Source::Mock(_) => return Ok(()),
+        };
+        if unsafe { CloseHandle(handle) } == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Collects the remaining entries into any [`FromIterator`] collection, like a `HashSet` or
+    /// `BTreeSet`, and closes the snapshot handle right after, before returning.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// use std::collections::HashSet;
+    ///
+    /// let modules: HashSet<_> = tlhelp32::Snapshot::new_module(pid)?.collect_into();
+    /// ```
+    pub fn collect_into<C: FromIterator<T>>(self) -> C {
+        // `collect` consumes the snapshot, closing it before the collection is returned
+        self.collect()
+    }
+
+    /// Collects the remaining entries and closes the snapshot. Unlike the snapshot itself, which
+    /// holds a raw handle, the returned vector is [`Send`] and can be handed to another thread.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let processes = tlhelp32::Snapshot::new_process()?.into_owned();
+    /// std::thread::spawn(move || {
+    ///     for entry in processes {
+    ///         println!("{:?}", entry);
+    ///     }
+    /// });
+    /// ```
+    pub fn into_owned(self) -> Vec<T>
+    where
+        T: Clone + Send,
+    {
+        self.collect()
+    }
+
+    /// Collects the remaining entries into an exactly sized boxed slice and closes the snapshot.
+    /// Unlike a collected [`Vec`], which can have up to twice the capacity it needs after growing,
+    /// this holds no excess capacity, which adds up when many snapshots are retained.
+    pub fn into_boxed_slice(self) -> Box<[T]> {
+        self.collect::<Vec<_>>().into_boxed_slice()
+    }
+
+    /// Collects the remaining entries in reverse order and closes the snapshot.
+    /// Toolhelp only walks a snapshot forward, which is why [`Snapshot`] isn't a
+    /// [`DoubleEndedIterator`]; this is the practical alternative. Processes are usually listed
+    /// roughly in the order they were started, so reversing a process snapshot puts the most
+    /// recently started ones first, though the OS doesn't guarantee any order.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let newest = tlhelp32::Snapshot::new_process()?.collect_reversed();
+    /// ```
+    pub fn collect_reversed(self) -> Vec<T> {
+        let mut entries: Vec<_> = self.collect();
+        entries.reverse();
+        entries
+    }
+
+    /// Yields at most `max` of the remaining entries. Unlike [`take`](Iterator::take), the
+    /// snapshot is closed as soon as the limit is hit, not only once the returned iterator is
+    /// dropped, so scanners holding on to it don't keep the handle open.
+    pub fn limited(self, max: usize) -> impl Iterator<Item = T> {
+        let mut snapshot = if max == 0 { None } else { Some(self) };
+        let mut remaining = max;
+        iter::from_fn(move || {
+            let entry = snapshot.as_mut()?.next();
+            remaining -= 1;
+            if entry.is_none() || remaining == 0 {
+                // closes the snapshot
+                snapshot = None;
+            }
+            entry
+        })
+    }
+
+    /// Pairs the remaining entries with their one-based position, for numbered listings. The
+    /// snapshot is closed as soon as the returned iterator is exhausted or dropped.
+    ///
+    /// The position is only meaningful within this snapshot. Toolhelp doesn't document the order
+    /// of processes and threads, it is neither sorted by id nor stable across snapshots, so the
+    /// same process can get a different number every time. Modules are the exception, they are
+    /// listed in load order with the main executable first.
+    pub fn enumerate_entries(self) -> impl Iterator<Item = (usize, T)> {
+        self.enumerate().map(|(idx, entry)| (idx + 1, entry))
+    }
+}
+
+impl Snapshot<ProcessEntry> {
+    /// Creates a new [`ProcessEntry`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPPROCESS` flag.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create a
+    /// [`Snapshot`]
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for entry in tlhelp32::Snapshot::new_process()? {
+    ///     println!("{:?}", entry);
+    /// }
+    /// ```
+    pub fn new_process() -> Result<Self> {
+        Ok(Self::new(0)?)
+    }
+
+    /// Collects the snapshot into a [`ProcessEntryIndexed`] for matching processes by name
+    /// repeatedly.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let processes = tlhelp32::Snapshot::new_process()?.index_by_name();
+    /// let explorer = processes.find("explorer.exe");
+    /// let svchosts = processes.find_all("svchost.exe").count();
+    /// ```
+    pub fn index_by_name(self) -> ProcessEntryIndexed {
+        ProcessEntryIndexed {
+            entries: self
+                .map(|entry| (wide_uppercase(entry.sz_exe_file.as_slice()), entry))
+                .collect(),
+        }
+    }
+
+    /// Walks the snapshot without building a [`ProcessEntry`] for every process, calling `f` with
+    /// the process id, thread count, parent process id, base priority and executable name of each.
+    /// The name is borrowed from the entry buffer the snapshot reuses for all processes, so unlike
+    /// iterating the snapshot, this doesn't allocate per process.
+    /// The borrowed name is only valid for the duration of the call, as the next process
+    /// overwrites the buffer.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// tlhelp32::Snapshot::new_process()?.for_each_reusing(|pid, _, _, _, name| {
+    ///     println!("{}: {}", pid, name.to_string_lossy());
+    /// });
+    /// ```
+    pub fn for_each_reusing<F: FnMut(u32, u32, u32, i32, &U16CStr)>(self, mut f: F) {
+        self.find_reusing(|pid, threads, ppid, priority, name| {
+            f(pid, threads, ppid, priority, name);
+            false
+        });
+    }
+
+    /// Like [`Iterator::find`], but the predicate gets the same borrowed fields as with
+    /// [`for_each_reusing`](Self::for_each_reusing), so a [`ProcessEntry`] is only built for the
+    /// matching process and the others cost no allocations.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let init = tlhelp32::Snapshot::new_process()?.find_reusing(|_, _, ppid, _, _| ppid == 0);
+    /// ```
+    pub fn find_reusing<F: FnMut(u32, u32, u32, i32, &U16CStr) -> bool>(
+        mut self,
+        mut predicate: F,
+    ) -> Option<ProcessEntry> {
+        self.fetch_first();
+        while let Some(raw) = self.current.as_ref() {
+            if predicate(
+                raw.th32ProcessID,
+                raw.cntThreads,
+                raw.th32ParentProcessID,
+                raw.pcPriClassBase,
+                U16CStr::from_slice_with_nul(&raw.szExeFile).unwrap_or_default(),
+            ) {
+                let raw = *raw;
+                return Some(self.source.entries().convert(raw));
+            }
+            self.advance();
+        }
+        None
+    }
+}
+
+impl Snapshot<HeapList> {
+    /// Creates a new [`HeapList`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPHEAPLIST` flag.
+    /// Passing [`CURRENT_PROCESS`] snapshots the heaps of the calling process.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create a
+    /// [`Snapshot`].
+    /// Passing the id of the System process (`4`) fails with [`ErrorKind::PermissionDenied`].
+    /// Under Wine, where heap snapshots are only partially implemented, the failure of its stub,
+    /// `ERROR_CALL_NOT_IMPLEMENTED`, is reported as [`ErrorKind::Unsupported`], see [`is_wine`].
+    /// Any other failure is reported as is.
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for heap_list in tlhelp32::Snapshot::new_heap_list(pid)? {
+    ///     for heap_entry in heap_list {
+    ///         println!("{:?}", heap_entry);
+    ///     }
+    /// }
+    /// ```
+    pub fn new_heap_list(pid: u32) -> Result<Self> {
+        if pid == SYSTEM_PROCESS {
+            return Err(SnapshotError::new(
+                pid,
+                HeapList::FLAGS,
+                Error::new(
+                    ErrorKind::PermissionDenied,
+                    "cannot snapshot heaps of the System process",
+                ),
+            )
+            .into());
+        }
+        Self::new(pid).map_err(|e| {
+            // only the failure of Wine's stub, real errors like access denied are passed through
+            let stubbed = e.source.raw_os_error() == Some(ERROR_CALL_NOT_IMPLEMENTED as i32);
+            if stubbed && is_wine() {
+                let source = Error::new(
+                    ErrorKind::Unsupported,
+                    format!("heap snapshots are not supported under Wine: {}", e.source),
+                );
+                SnapshotError { source, ..e }.into()
+            } else {
+                e.into()
+            }
+        })
+    }
+
+    /// Creates a new [`HeapList`] [`Snapshot`] of the calling process.
+    /// The snapshot is a point-in-time view, heaps and blocks allocated or freed while walking it,
+    /// including by the walk itself, may or may not be reflected.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create a
+    /// [`Snapshot`]
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let total: usize = tlhelp32::Snapshot::new_heap_list_self()?
+    ///     .flatten()
+    ///     .map(|heap_entry| heap_entry.block_size)
+    ///     .sum();
+    /// println!("{} bytes on the heaps", total);
+    /// ```
+    pub fn new_heap_list_self() -> Result<Self> {
+        Ok(Self::new(unsafe { GetCurrentProcessId() })?)
+    }
+}
+
+impl Snapshot<ModuleEntry> {
+    /// Creates a new [`ModuleEntry`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPMODULE` and `TH32CS_SNAPMODULE32` flags.
+    /// Some Windows versions fail the combined flags with `ERROR_PARTIAL_COPY` for processes of a
+    /// different bitness, in which case `TH32CS_SNAPMODULE` and then `TH32CS_SNAPMODULE32` are
+    /// tried on their own. The flags that ended up working are reported by
+    /// [`flags`](Self::flags).
+    /// Passing [`CURRENT_PROCESS`] snapshots the modules of the calling process, whose actual id
+    /// is then reported by the entries' `process_id`.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create a
+    /// [`Snapshot`].
+    /// Passing the id of the System process (`4`) fails with [`ErrorKind::PermissionDenied`].
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for mod_entry in tlhelp32::Snapshot::new_module(entry.process_id)? {
+    ///     println!("{:?}", mod_entry);
+    /// }
+    /// ```
+    pub fn new_module(pid: u32) -> Result<Self> {
+        if pid == SYSTEM_PROCESS {
+            return Err(SnapshotError::new(
+                pid,
+                ModuleEntry::FLAGS,
+                Error::new(
+                    ErrorKind::PermissionDenied,
+                    "cannot snapshot modules of the System process",
+                ),
+            )
+            .into());
+        }
+        let is_partial_copy =
+            |e: &SnapshotError| e.source.raw_os_error() == Some(ERROR_PARTIAL_COPY as i32);
+        match Self::new(pid) {
+            Err(ref e) if is_partial_copy(e) => (),
+            res => return Ok(res?),
+        }
+        match Self::with_flags(TH32CS_SNAPMODULE, pid) {
+            Err(ref e) if is_partial_copy(e) => Ok(Self::with_flags(TH32CS_SNAPMODULE32, pid)?),
+            res => Ok(res?),
+        }
+    }
+
+    /// Returns the snapshot unchanged. This exists to make the ordering contract of module
+    /// snapshots explicit at the call site: the main executable image is always yielded first,
+    /// followed by the remaining modules in the order they were loaded.
+    pub fn in_load_order(self) -> Self {
+        self
+    }
+
+    /// Skips the main executable image so that only the modules it loaded are yielded.
+    /// See [`in_load_order`](Self::in_load_order) for the ordering this relies on.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for dll in tlhelp32::Snapshot::new_module(pid)?.skip_main() {
+    ///     println!("{:?}", dll);
+    /// }
+    /// ```
+    pub fn skip_main(self) -> Skip<Self> {
+        self.skip(1)
+    }
+
+    /// Consumes the snapshot and returns the distinct, sorted set of its module names.
+    /// Names that aren't valid UTF-16 are converted lossily, so two distinct invalid names may
+    /// collapse into the same entry.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let before = tlhelp32::Snapshot::new_module(pid)?.module_names();
+    /// // ...
+    /// let after = tlhelp32::Snapshot::new_module(pid)?.module_names();
+    /// for name in after.difference(&before) {
+    ///     println!("newly loaded: {}", name);
+    /// }
+    /// ```
+    pub fn module_names(self) -> BTreeSet<String> {
+        self.map(|entry| entry.sz_module.to_string_lossy())
+            .collect()
+    }
+}
+
+impl Snapshot<ThreadEntry> {
+    /// Creates a new [`ThreadEntry`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPTHREAD` flag.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create a
+    /// [`Snapshot`]
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for thread_entry in tlhelp32::Snapshot::new_thread()? {
+    ///     println!("{:?}", mod_entry);
+    /// }
+    /// ```
+    pub fn new_thread() -> Result<Self> {
+        Ok(Self::new(0)?)
+    }
+
+    /// Creates a new [`ThreadEntry`] [`Snapshot`] and filters it down to the threads of the
+    /// calling process. Toolhelp always snapshots the threads of the whole system, there is no
+    /// per-process thread snapshot, so this still walks every thread.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create a
+    /// [`Snapshot`]
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for thread_entry in tlhelp32::Snapshot::new_thread_self()? {
+    ///     println!("{:?}", thread_entry);
+    /// }
+    /// ```
+    pub fn new_thread_self() -> Result<impl Iterator<Item = ThreadEntry>> {
+        let pid = unsafe { GetCurrentProcessId() };
+        Ok(Self::new_thread()?.filter(move |entry| entry.owner_process_id == pid))
+    }
+
+    /// Pairs every thread of the snapshot with the process owning it, for listing threads
+    /// together with their process names without looking up every owner separately. This takes
+    /// a single process [`Snapshot`] up front and maps the owners by their process id.
+    ///
+    /// The process snapshot is taken after the thread snapshot, so a thread whose process exited
+    /// in between is paired with [`None`]. As process ids are reused, a thread might in rare
+    /// cases even be paired with a new process that got the id of its exited owner.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to create the
+    /// process [`Snapshot`]
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// for (thread, owner) in tlhelp32::Snapshot::new_thread()?.with_owners()? {
+    ///     let name = owner.map(|p| p.sz_exe_file.to_string_lossy());
+    ///     println!("{} {:?}", thread.thread_id, name);
+    /// }
+    /// ```
+    pub fn with_owners(self) -> Result<impl Iterator<Item = (ThreadEntry, Option<ProcessEntry>)>> {
+        let owners: HashMap<u32, ProcessEntry> = Snapshot::new_process()?
+            .map(|entry| (entry.process_id, entry))
+            .collect();
+        Ok(self.map(move |thread| {
+            let owner = owners.get(&thread.owner_process_id).cloned();
+            (thread, owner)
+        }))
+    }
+}
+
+impl<T: TagTl32> Iterator for Snapshot<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fetch_first();
+        let raw = self.current?;
+        let mut entry = self.source.entries().convert(raw);
+        if self.at_first {
+            entry.mark_first();
+        }
+        self.advance();
+        Some(entry)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.fetch_first();
+        // advance the raw cursor without converting the skipped entries
+        for _ in 0..n {
+            if !self.advance() {
+                return None;
+            }
+        }
+        self.next()
+    }
+}
+
+impl<T: TagTl32> FusedIterator for Snapshot<T> {}
+
+impl<T: TagTl32> Drop for Snapshot<T> {
+    fn drop(&mut self) {
+        let snapshot = match self.source {
+            Source::Handle(snapshot) => snapshot,
+            Source::Mock(_) => return,
+        };
+        let closed = unsafe { CloseHandle(snapshot) };
+        // a failing close means the handle was invalid or already closed, which is a bug
+        debug_assert!(
+            closed != 0,
+            "failed to close the snapshot handle: {}",
+            Error::last_os_error()
+        );
+    }
+}
+
+/// A single toolhelp snapshot of processes, threads, modules and heaps together, which can be
+/// [viewed](Self::view) as any of the entry types. An inspector that needs all of them takes one
+/// snapshot this way instead of one per entry type, and the entries of the different views are
+/// consistent with each other as they are all from the same point in time.
+/// The handle is closed once the combined snapshot is dropped.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// use tlhelp32::{CombinedSnapshot, ModuleEntry, ThreadEntry};
+///
+/// let mut snapshot = CombinedSnapshot::new(pid)?;
+/// let threads = snapshot.view::<ThreadEntry>().count();
+/// let modules: Vec<_> = snapshot.view::<ModuleEntry>().collect();
+/// ```
+#[derive(Debug)]
+pub struct CombinedSnapshot {
+    snapshot: OwnedHandle,
+    process_id: u32,
+    flags: SnapshotFlags,
+}
+
+impl CombinedSnapshot {
+    /// Takes a snapshot of all processes and threads, and of the modules and heaps of the given
+    /// process. This is equal to creating a snapshot with the `TH32CS_SNAPALL` and
+    /// `TH32CS_SNAPMODULE32` flags.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create
+    /// the snapshot. Like [`Snapshot::new_module`] this fails with `ERROR_PARTIAL_COPY` for a 64-bit
+    /// process if the caller is 32-bit, and might do so for a process that is still starting up.
+    pub fn new(pid: u32) -> Result<Self> {
+        Self::with_flags(
+            SnapshotFlags::HEAPLIST
+                | SnapshotFlags::PROCESS
+                | SnapshotFlags::THREAD
+                | SnapshotFlags::MODULE
+                | SnapshotFlags::MODULE32,
+            pid,
+        )
+    }
+
+    /// Takes a snapshot with the given flags, for including only some of the entry types.
+    /// Views of the entry types that weren't included are empty.
+    /// # Errors
+    /// This function fails with a [`SnapshotError`] as the inner error if it is unable to create
+    /// the snapshot
+    pub fn with_flags(flags: SnapshotFlags, pid: u32) -> Result<Self> {
+        match unsafe { CreateToolhelp32Snapshot(flags.bits(), pid) } {
+            INVALID_HANDLE_VALUE => Err(SnapshotError::last_os_error(pid, flags.bits()).into()),
+            snapshot => Ok(CombinedSnapshot {
+                snapshot: unsafe { OwnedHandle::from_raw_handle(snapshot as _) },
+                process_id: pid,
+                flags,
+            }),
+        }
+    }
+
+    /// The flags the snapshot was created with.
+    pub fn snapshot_flags(&self) -> SnapshotFlags {
+        self.flags
+    }
+
+    /// Iterates the entries of type `T` of the snapshot from the start. Every view restarts the
+    /// walk with the `*32First` function of `T`, so the snapshot can be viewed any number of
+    /// times without being recreated.
+    /// The entry types keep their walks apart, but all walks of the same type share a single
+    /// cursor stored in the snapshot, so only one view can be borrowed at a time.
+    pub fn view<T: TagTl32>(&mut self) -> SnapshotView<'_, T> {
+        let snapshot = unsafe {
+            Snapshot::from_valid_handle(
+                raw(&self.snapshot),
+                Snapshot::<T>::resolve_pid(self.process_id),
+                T::FLAGS,
+            )
+        };
+        SnapshotView {
+            snapshot: ManuallyDrop::new(snapshot),
+            _combined: PhantomData,
+        }
+    }
+}
+
+/// The entries of one type in a [`CombinedSnapshot`], created by [`CombinedSnapshot::view`].
+pub struct SnapshotView<'a, T: TagTl32> {
+    // borrows the handle of the combined snapshot, which closes it
+    snapshot: ManuallyDrop<Snapshot<T>>,
+    _combined: PhantomData<&'a mut CombinedSnapshot>,
+}
+
+impl<T: TagTl32> Iterator for SnapshotView<'_, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.snapshot.next()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.snapshot.nth(n)
+    }
 }
 
-impl TagTl32 for ThreadEntry {
-    type Raw = THREADENTRY32;
-    const FLAGS: u32 = TH32CS_SNAPTHREAD;
-    const ITER_FIRST: Tl32helpFunc<Self::Raw> = Thread32First;
-    const ITER_NEXT: Tl32helpFunc<Self::Raw> = Thread32Next;
+impl<T: TagTl32> FusedIterator for SnapshotView<'_, T> {}
 
-    #[inline]
-    fn init_raw() -> Self::Raw {
-        Self::Raw {
-            dwSize: mem::size_of::<Self::Raw>() as u32,
-            ..unsafe { mem::uninitialized() }
+impl<T: TagTl32> fmt::Debug for SnapshotView<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnapshotView")
+            .field("snapshot", &self.snapshot.handle())
+            .field("process_id", &self.snapshot.process_id)
+            .finish()
+    }
+}
+
+/// Takes a process [`Snapshot`] and collects its entries into a list of [`ProcessInfo`]s.
+/// Executable names that aren't valid UTF-16 are converted lossily.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn process_list() -> Result<Vec<ProcessInfo>> {
+    Ok(Snapshot::new_process()?.map(ProcessInfo::from).collect())
+}
+
+/// The order of the processes returned by [`process_list_sorted`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProcessSortKey {
+    /// Ascending by process id.
+    Pid,
+    /// Ascending by executable name ignoring case, processes with the same name by process id.
+    Name,
+}
+
+/// Like [`process_list`], but sorted by `key`. The order of a raw snapshot isn't guaranteed to be
+/// stable between calls, this gives a deterministic order, for example for display.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn process_list_sorted(key: ProcessSortKey) -> Result<Vec<ProcessInfo>> {
+    let mut processes = process_list()?;
+    match key {
+        ProcessSortKey::Pid => processes.sort_by_key(|info| info.process_id),
+        ProcessSortKey::Name => {
+            processes.sort_by_cached_key(|info| (info.exe_file.to_lowercase(), info.process_id))
         }
     }
+    Ok(processes)
+}
 
-    #[inline]
-    fn from_raw(raw: Self::Raw) -> Self {
-        ThreadEntry {
-            thread_id: raw.th32ThreadID,
-            owner_process_id: raw.th32OwnerProcessID,
-            base_pri: raw.tpBasePri,
+/// Takes a process [`Snapshot`] and resolves the full image path of every process, like
+/// [`ProcessEntry::full_image_path`] does, but with a single buffer reused for all of them.
+/// Processes that can't be opened or queried, usually because access to them was denied or
+/// because they exited in the meantime, are left out. The System and Idle processes never have
+/// an image path.
+///
+/// This opens every process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// for (pid, path) in tlhelp32::process_paths()? {
+///     println!("{:>6} {}", pid, path.display());
+/// }
+/// ```
+pub fn process_paths() -> Result<Vec<(u32, PathBuf)>> {
+    // the maximum length of an extended-length path
+    const MAX_LEN: usize = 0x8000;
+    let mut buf = vec![0u16; 260];
+    let mut paths = Vec::new();
+    for entry in Snapshot::new_process()? {
+        let process = match open_process(PROCESS_QUERY_LIMITED_INFORMATION, entry.process_id) {
+            Ok(process) => process,
+            Err(_) => continue,
+        };
+        loop {
+            let mut len = buf.len() as u32;
+            if unsafe { QueryFullProcessImageNameW(raw(&process), 0, buf.as_mut_ptr(), &mut len) }
+                != 0
+            {
+                paths.push((
+                    entry.process_id,
+                    OsString::from_wide(&buf[..len as usize]).into(),
+                ));
+                break;
+            }
+            match unsafe { GetLastError() } {
+                ERROR_INSUFFICIENT_BUFFER if buf.len() < MAX_LEN => {
+                    buf.resize((buf.len() * 2).min(MAX_LEN), 0)
+                }
+                _ => break,
+            }
         }
     }
+    Ok(paths)
 }
 
-/// An iterator for the Toolhelp32Snapshot Windows API.
-/// You create them by calling the appropriate `new_*` methods.
-#[derive(Debug)]
-pub struct Snapshot<T: TagTl32> {
-    snapshot: HANDLE,
-    current: Option<T::Raw>,
+/// Takes a process [`Snapshot`], samples the CPU time of every process twice, `sample` apart,
+/// and returns every process that is still running with the share of the total CPU time it used
+/// in between, in percent. The share is relative to all logical processors, so it is at most
+/// `100.0` even for processes using several of them, like the task manager shows it.
+/// **This blocks the calling thread for `sample`.**
+///
+/// Processes that appeared between the samples and those whose times can't be queried, usually
+/// because access to them was denied, are listed with `0.0`. The processes of the first sample
+/// are kept open until the second one, so a process that exited in between can't be confused
+/// with a new one reusing its id.
+///
+/// This opens every process with the `PROCESS_QUERY_LIMITED_INFORMATION` access right.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create either
+/// [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let mut processes = tlhelp32::processes_with_cpu(Duration::from_secs(1))?;
+/// processes.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+/// for (info, cpu) in processes.iter().take(10) {
+///     println!("{:>6.2}% {}", cpu, info.exe_file);
+/// }
+/// ```
+pub fn processes_with_cpu(sample: Duration) -> Result<Vec<(ProcessInfo, f64)>> {
+    fn cpu_intervals(process: &OwnedHandle) -> Option<u64> {
+        let [_, _, kernel, user] = process_times(raw(process)).ok()?;
+        Some(filetime_intervals(kernel) + filetime_intervals(user))
+    }
+
+    let start = Instant::now();
+    let first: HashMap<_, _> = Snapshot::new_process()?
+        .filter_map(|entry| {
+            let process = open_process(PROCESS_QUERY_LIMITED_INFORMATION, entry.process_id).ok()?;
+            let intervals = cpu_intervals(&process)?;
+            Some((entry.process_id, (process, intervals)))
+        })
+        .collect();
+    thread::sleep(sample);
+    let elapsed = start.elapsed();
+    let processors = unsafe { GetActiveProcessorCount(ALL_PROCESSOR_GROUPS) }.max(1);
+    let available =
+        elapsed.as_secs_f64() * FILETIME_INTERVALS_PER_SEC as f64 * f64::from(processors);
+
+    Ok(Snapshot::new_process()?
+        .map(|entry| {
+            let cpu = first
+                .get(&entry.process_id)
+                .and_then(|(process, before)| {
+                    let used = cpu_intervals(process)?.saturating_sub(*before);
+                    Some((used as f64 / available * 100.0).min(100.0))
+                })
+                .unwrap_or(0.0);
+            (entry.into(), cpu)
+        })
+        .collect())
 }
 
-impl<T: TagTl32> Snapshot<T> {
-    #[inline]
-    fn new(pid: u32) -> Result<Self> {
-        unsafe { Self::from_handle(CreateToolhelp32Snapshot(T::FLAGS, pid)) }
+/// Runs [`process_list`] on tokio's blocking thread pool so it can be awaited from async code
+/// without stalling the runtime.
+/// # Errors
+/// This function fails if [`process_list`] fails or if the blocking task panicked or got cancelled.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// for info in tlhelp32::process_list_async().await? {
+///     println!("{:?}", info);
+/// }
+/// ```
+#[cfg(feature = "tokio")]
+pub async fn process_list_async() -> Result<Vec<ProcessInfo>> {
+    tokio::task::spawn_blocking(process_list)
+        .await
+        .map_err(Error::other)?
+}
+
+/// Takes a single process [`Snapshot`] and pairs every process with the executable name of its
+/// parent, or [`None`] if the parent isn't part of the snapshot, usually because it has exited.
+/// Names that aren't valid UTF-16 are converted lossily.
+///
+/// Since all names come from the same snapshot the pairing is consistent for one point in time.
+/// Windows doesn't update `parent_process_id` when the parent exits though, so if its id got
+/// reused in the meantime, the name of an unrelated process is reported.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn processes_with_parent_names() -> Result<Vec<(ProcessEntry, Option<String>)>> {
+    let processes: Vec<_> = Snapshot::new_process()?.collect();
+    let names: HashMap<_, _> = processes
+        .iter()
+        .map(|entry| (entry.process_id, entry.sz_exe_file.to_string_lossy()))
+        .collect();
+    Ok(processes
+        .into_iter()
+        .map(|entry| {
+            let parent = names.get(&entry.parent_process_id).cloned();
+            (entry, parent)
+        })
+        .collect())
+}
+
+/// Takes a single process [`Snapshot`] and orders the processes as a tree: every process is
+/// followed by its children, depth first, and paired with its depth in the tree. Processes whose
+/// parent isn't part of the snapshot, usually because it has exited, are roots with a depth of
+/// `0`.
+/// As with [`processes_with_parent_names`], a reused parent id attaches a process to an
+/// unrelated parent.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// for (depth, entry) in tlhelp32::process_tree()? {
+///     println!("{:indent$}{}", "", entry.sz_exe_file.to_string_lossy(), indent = depth * 2);
+/// }
+/// ```
+pub fn process_tree() -> Result<Vec<(usize, ProcessEntry)>> {
+    let processes: Vec<_> = Snapshot::new_process()?.collect();
+    let pids: HashSet<_> = processes.iter().map(|entry| entry.process_id).collect();
+    let mut children: HashMap<_, Vec<_>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (idx, entry) in processes.iter().enumerate() {
+        // the idle process is its own parent
+        if entry.parent_process_id != entry.process_id && pids.contains(&entry.parent_process_id) {
+            children
+                .entry(entry.parent_process_id)
+                .or_default()
+                .push(idx);
+        } else {
+            roots.push(idx);
+        }
     }
 
-    /// Creates a snapshot from a given handle. Avoid using this unless you have a specific reason to.
-    /// # Safety
-    /// This function does not check whether the generic type and the flags belong together.
-    /// If used incorrectly this will produce an iterator that returns [`None`] from the very beginning.
-    pub unsafe fn from_handle(snapshot: HANDLE) -> Result<Self> {
-        match snapshot {
-            INVALID_HANDLE_VALUE => Err(Error::last_os_error()),
-            snapshot => {
-                let mut entry = T::init_raw();
-                let current = if T::ITER_FIRST(snapshot, &mut entry) == 0 {
-                    None
-                } else {
-                    Some(entry)
-                };
-                Ok(Snapshot { snapshot, current })
+    let mut visited = vec![false; processes.len()];
+    let mut order = Vec::with_capacity(processes.len());
+    let mut stack: Vec<_> = roots.into_iter().rev().map(|idx| (0, idx)).collect();
+    loop {
+        while let Some((depth, idx)) = stack.pop() {
+            if mem::replace(&mut visited[idx], true) {
+                continue;
+            }
+            order.push((depth, idx));
+            if let Some(children) = children.get(&processes[idx].process_id) {
+                stack.extend(children.iter().rev().map(|&child| (depth + 1, child)));
             }
         }
+        // reused parent ids can form cycles that aren't reachable from any root
+        match visited.iter().position(|&visited| !visited) {
+            Some(idx) => stack.push((0, idx)),
+            None => break,
+        }
     }
 
-    /// Retrieves the windows snapshot handle
-    pub fn handle(&self) -> HANDLE {
-        self.snapshot
+    let mut processes: Vec<_> = processes.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .filter_map(|(depth, idx)| Some((depth, processes[idx].take()?)))
+        .collect())
+}
+
+/// Gathers everything about the process with the given id in one call: its snapshot entry, full
+/// image path, command line, session id, bitness and creation time.
+/// The individual queries failing, usually because access to the process is denied, doesn't fail
+/// the whole call, their errors are stored in the [`ProcessDetails`] instead.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create the process
+/// [`Snapshot`], or with [`ErrorKind::NotFound`] if there is no process with the given id.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let details = tlhelp32::process_details(pid)?;
+/// match &details.command_line {
+///     Ok(line) => println!("{}", line),
+///     Err(e) => println!("<{}>", e),
+/// }
+/// ```
+pub fn process_details(pid: u32) -> Result<ProcessDetails> {
+    let entry = Snapshot::new_process()?
+        .find(|entry| entry.process_id == pid)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no process with the given id exists"))?;
+    Ok(ProcessDetails {
+        image_path: entry.full_image_path(),
+        command_line: entry.command_line(),
+        session_id: entry.session_id(),
+        is_wow64: entry.is_wow64(),
+        creation_time: entry.creation_time(),
+        entry,
+    })
+}
+
+/// Counts the threads the process currently owns by walking a thread [`Snapshot`].
+/// As this is a live count, it can differ from the `cnt_threads` of a previously taken
+/// [`ProcessEntry`]. A process that doesn't exist has no threads.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn thread_count(pid: u32) -> Result<u32> {
+    Ok(Snapshot::new_thread()?
+        .filter(|entry| entry.owner_process_id == pid)
+        .count() as u32)
+}
+
+/// Returns the processes that have more than `n` threads, sorted by their thread count in
+/// descending order.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn processes_over_thread_count(n: u32) -> Result<Vec<ProcessEntry>> {
+    let mut processes: Vec<_> = Snapshot::new_process()?
+        .filter(|entry| entry.cnt_threads > n)
+        .collect();
+    processes.sort_by_key(|entry| Reverse(entry.cnt_threads));
+    Ok(processes)
+}
+
+/// Returns the process with the most threads according to the `cnt_threads` of a single process
+/// [`Snapshot`]. Of several processes with the same count the first one in the snapshot is
+/// returned. This is only [`None`] if the snapshot is empty, which doesn't happen in practice.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn process_with_most_threads() -> Result<Option<ProcessEntry>> {
+    // `max_by_key` returns the last of several maxima, `min_by_key` the first of several minima
+    Ok(Snapshot::new_process()?.min_by_key(|entry| Reverse(entry.cnt_threads)))
+}
+
+/// Takes a module [`Snapshot`] of each of the given processes and chains their modules into one
+/// iterator, in the order of `pids`. Every entry carries its `process_id`, so the modules can be
+/// grouped again.
+/// Processes whose modules can't be snapshotted, because they exited or access to them was
+/// denied, are silently skipped. All snapshots are taken up front, so the modules of the
+/// different processes are from roughly the same point in time.
+pub fn modules_for_pids(pids: &[u32]) -> Result<impl Iterator<Item = ModuleEntry>> {
+    let snapshots: Vec<_> = pids
+        .iter()
+        .filter_map(|&pid| Snapshot::new_module(pid).ok())
+        .collect();
+    Ok(snapshots.into_iter().flatten())
+}
+
+/// The modules of several processes as collected by [`modules_by_process`].
+#[derive(Debug, Default)]
+pub struct ModulesByProcess {
+    /// The modules of every process that could be snapshotted, in load order.
+    pub modules: HashMap<u32, Vec<ModuleEntry>>,
+    /// The processes that couldn't be snapshotted, together with the reason, in the order they
+    /// were passed.
+    pub failures: Vec<(u32, Error)>,
+}
+
+/// Controls how the bulk enumerators like [`modules_by_process_with`] treat processes they fail
+/// to snapshot.
+/// The default options record every failure, so nothing is lost silently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SnapshotOptions {
+    /// Leave out processes that deny access, with [`ErrorKind::PermissionDenied`], instead of
+    /// recording them as failures. Scans of all processes run into many of these when not
+    /// elevated, which usually aren't interesting, but skipping them also hides when the scan
+    /// misses processes it was expected to see.
+    pub skip_access_denied: bool,
+}
+
+impl SnapshotOptions {
+    /// Collects the module snapshots of several processes, handling the failures as picked.
+    fn collect<I>(&self, snapshots: I) -> ModulesByProcess
+    where
+        I: IntoIterator<Item = (u32, Result<Vec<ModuleEntry>>)>,
+    {
+        let mut result = ModulesByProcess::default();
+        for (pid, snapshot) in snapshots {
+            match snapshot {
+                Ok(modules) => {
+                    result.modules.insert(pid, modules);
+                }
+                Err(e) if self.skip_access_denied && e.kind() == ErrorKind::PermissionDenied => (),
+                Err(e) => result.failures.push((pid, e)),
+            }
+        }
+        result
     }
 }
 
-impl Snapshot<ProcessEntry> {
-    /// Creates a new [`ProcessEntry`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPPROCESS` flag.
-    /// # Errors
-    /// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
-    ///
-    /// # Usage
-    ///
-    /// ```rust,no_run
-    /// for entry in tlhelp32::Snapshot::new_process()? {
-    ///     println!("{:?}", entry);
-    /// }
-    /// ```
-    pub fn new_process() -> Result<Self> {
-        Self::new(0)
+/// Takes a module [`Snapshot`] of each of the given processes and collects their modules into a
+/// map keyed by process id, keeping the load order of each process's modules.
+/// Processes that can't be snapshotted, usually because they exited or access to them was
+/// denied, are left out of the map and recorded in
+/// [`failures`](ModulesByProcess::failures) instead.
+pub fn modules_by_process(pids: &[u32]) -> Result<ModulesByProcess> {
+    modules_by_process_with(pids, SnapshotOptions::default())
+}
+
+/// Like [`modules_by_process`], but with the failures handled as picked by `opts`.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let opts = tlhelp32::SnapshotOptions {
+///     skip_access_denied: true,
+/// };
+/// let modules = tlhelp32::modules_by_process_with(&pids, opts)?;
+/// ```
+pub fn modules_by_process_with(pids: &[u32], opts: SnapshotOptions) -> Result<ModulesByProcess> {
+    Ok(opts.collect(
+        pids.iter()
+            .map(|&pid| (pid, Snapshot::new_module(pid).map(Iterator::collect))),
+    ))
+}
+
+/// The changes to the modules of a process relative to a baseline, see [`module_diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleDiff {
+    /// The modules that are loaded now but weren't in the baseline, in load order.
+    pub loaded: Vec<ModuleEntry>,
+    /// The modules of the baseline that aren't loaded anymore, in baseline order.
+    pub unloaded: Vec<ModuleEntry>,
+}
+
+impl ModuleDiff {
+    /// Checks whether the modules are the same as in the baseline.
+    pub fn is_empty(&self) -> bool {
+        self.loaded.is_empty() && self.unloaded.is_empty()
     }
 }
 
-impl Snapshot<HeapList> {
-    /// Creates a new [`HeapList`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPHEAPLIST` flag.
-    /// # Errors
-    /// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
-    /// # Usage
-    ///
-    /// ```rust,no_run
-    /// for heap_list in tlhelp32::Snapshot::new_heap_list(pid)? {
-    ///     for heap_entry in heap_list {
-    ///         println!("{:?}", heap_entry);
-    ///     }
-    /// }
-    /// ```
-    pub fn new_heap_list(pid: u32) -> Result<Self> {
-        Self::new(pid)
+/// Takes a module [`Snapshot`] of the process and compares it against `baseline`, usually the
+/// modules collected from an earlier snapshot of the same process. Modules are matched by their
+/// [equality](ModuleEntry#impl-PartialEq), that is by process id, base address and name, so a
+/// module that was unloaded and loaded again at a different address shows up in both lists.
+/// Capturing a baseline at a trusted moment and diffing against it periodically reveals DLLs
+/// injected in the meantime, the newly loaded entries carry their full information for
+/// inspection.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let baseline: Vec<_> = tlhelp32::Snapshot::new_module(pid)?.collect();
+/// // ...
+/// for module in tlhelp32::module_diff(pid, &baseline)?.loaded {
+///     println!("new module: {:?}", module.sz_exe_path);
+/// }
+/// ```
+pub fn module_diff(pid: u32, baseline: &[ModuleEntry]) -> Result<ModuleDiff> {
+    let current: Vec<ModuleEntry> = Snapshot::new_module(pid)?.collect();
+    let before: HashSet<&ModuleEntry> = baseline.iter().collect();
+    let after: HashSet<&ModuleEntry> = current.iter().collect();
+    let unloaded = baseline
+        .iter()
+        .filter(|module| !after.contains(module))
+        .cloned()
+        .collect();
+    let loaded = current
+        .iter()
+        .filter(|module| !before.contains(module))
+        .cloned()
+        .collect();
+    Ok(ModuleDiff { loaded, unloaded })
+}
+
+/// Takes a module [`Snapshot`] of the process and returns the modules whose `sz_exe_path` lies
+/// under `dir` or any of its subdirectories, for example only those loaded from
+/// `C:\Windows\System32`, or those loaded from a temporary directory, which hints at injection.
+/// The paths are compared ignoring case as by [`wide_eq_ignore_case`], with `/` treated like `\`
+/// and a leading `\\?\` ignored. They aren't resolved any further, so a `dir` given as a
+/// relative path, with `..` components or through a junction matches nothing.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// use std::path::Path;
+///
+/// let system = tlhelp32::modules_under_directory(pid, Path::new(r"C:\Windows\System32"))?;
+/// ```
+pub fn modules_under_directory(pid: u32, dir: &Path) -> Result<Vec<ModuleEntry>> {
+    let dir = normalize_path(dir.as_os_str().encode_wide());
+    Ok(Snapshot::new_module(pid)?
+        .filter(|module| {
+            let path = normalize_path(module.sz_exe_path.as_slice().iter().copied());
+            path_is_under(&path, &dir)
+        })
+        .collect())
+}
+
+/// Checks whether the normalized `path` lies within the normalized directory `dir`, ignoring case.
+fn path_is_under(path: &[u16], dir: &[u16]) -> bool {
+    path.len() > dir.len()
+        && path[dir.len()] == u16::from(b'\\')
+        && wide_slices_eq_ignore_case(&path[..dir.len()], dir)
+}
+
+/// The normalized Windows and system directories, see [`ModuleEntry::is_system_module`].
+/// Directories that can't be queried are left out.
+fn system_directories() -> &'static [Vec<u16>] {
+    fn query(get: unsafe extern "system" fn(*mut u16, u32) -> u32) -> Option<Vec<u16>> {
+        let mut buf = vec![0u16; 260];
+        loop {
+            // the required size including the terminator if the buffer is too small
+            match unsafe { get(buf.as_mut_ptr(), buf.len() as u32) } as usize {
+                0 => return None,
+                len if len < buf.len() => return Some(normalize_path(buf[..len].iter().copied())),
+                len => buf.resize(len, 0),
+            }
+        }
     }
+
+    static DIRECTORIES: OnceLock<Vec<Vec<u16>>> = OnceLock::new();
+    DIRECTORIES.get_or_init(|| {
+        vec![query(GetWindowsDirectoryW), query(GetSystemDirectoryW)]
+            .into_iter()
+            .flatten()
+            .collect()
+    })
 }
 
-impl Snapshot<ModuleEntry> {
-    /// Creates a new [`ModuleEntry`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPMODULE` and `TH32CS_SNAPMODULE32` flags.
-    /// # Errors
-    /// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
-    ///
-    /// # Usage
-    ///
-    /// ```rust,no_run
-    /// for mod_entry in tlhelp32::Snapshot::new_module(entry.process_id)? {
-    ///     println!("{:?}", mod_entry);
-    /// }
-    /// ```
-    pub fn new_module(pid: u32) -> Result<Self> {
-        Self::new(pid)
+/// Turns all separators of a path into backslashes and strips its verbatim prefix and trailing
+/// separators, so that paths can be compared by prefix.
+fn normalize_path(path: impl Iterator<Item = u16>) -> Vec<u16> {
+    const VERBATIM_PREFIX: [u16; 4] = [b'\\' as u16, b'\\' as u16, b'?' as u16, b'\\' as u16];
+
+    let mut path: Vec<u16> = path
+        .map(|c| {
+            if c == u16::from(b'/') {
+                u16::from(b'\\')
+            } else {
+                c
+            }
+        })
+        .collect();
+    if path.starts_with(&VERBATIM_PREFIX) {
+        path.drain(..VERBATIM_PREFIX.len());
+    }
+    while path.last() == Some(&u16::from(b'\\')) {
+        path.pop();
     }
+    path
 }
 
-impl Snapshot<ThreadEntry> {
-    /// Creates a new [`ThreadEntry`] [`Snapshot`]. This is equal to creating a snapshot with the `TH32CS_SNAPTHREAD` flag.
-    /// # Errors
-    /// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
-    ///
-    /// # Usage
-    ///
-    /// ```rust,no_run
-    /// for thread_entry in tlhelp32::Snapshot::new_thread()? {
-    ///     println!("{:?}", mod_entry);
-    /// }
-    /// ```
-    pub fn new_thread() -> Result<Self> {
-        Self::new(0)
+/// Takes a module [`Snapshot`] of every running process in parallel and returns their modules
+/// keyed by process id. Processes whose modules can't be snapshotted, usually because access to
+/// them was denied, are left out of the map.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create the process
+/// [`Snapshot`]
+#[cfg(feature = "rayon")]
+pub fn all_modules_parallel() -> Result<HashMap<u32, Vec<ModuleEntry>>> {
+    let opts = SnapshotOptions {
+        skip_access_denied: true,
+    };
+    // the failures besides denied access are left out of the map all the same
+    Ok(all_modules_parallel_with(opts)?.modules)
+}
+
+/// Like [`all_modules_parallel`], but the processes that can't be snapshotted are recorded in
+/// [`failures`](ModulesByProcess::failures) or skipped as picked by `opts`, instead of always
+/// being left out silently.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create the process
+/// [`Snapshot`]
+#[cfg(feature = "rayon")]
+pub fn all_modules_parallel_with(opts: SnapshotOptions) -> Result<ModulesByProcess> {
+    use rayon::prelude::*;
+
+    let pids: Vec<_> = Snapshot::new_process()?
+        .map(|entry| entry.process_id)
+        // the idle process would be interpreted as the current process
+        .filter(|&pid| pid != CURRENT_PROCESS)
+        .collect();
+    let snapshots: Vec<_> = pids
+        .into_par_iter()
+        .map(|pid| (pid, Snapshot::new_module(pid).map(Iterator::collect)))
+        .collect();
+    Ok(opts.collect(snapshots))
+}
+
+/// Compares two wide strings for equality ignoring case, the way Windows compares file names.
+/// This uses `CompareStringOrdinal`, which uppercases both strings with the invariant casing
+/// table rather than comparing them linguistically: `"É"` and `"é"` are equal, while the casing
+/// rules of a specific culture, like the Turkish dotless `"ı"` and `"I"`, don't apply.
+/// Surrogate pairs, that is characters outside of the Basic Multilingual Plane like emoji, are
+/// compared as they are.
+pub fn wide_eq_ignore_case(a: &U16CStr, b: &U16CStr) -> bool {
+    wide_slices_eq_ignore_case(a.as_slice(), b.as_slice())
+}
+
+fn wide_slices_eq_ignore_case(a: &[u16], b: &[u16]) -> bool {
+    const CSTR_EQUAL: i32 = 2;
+
+    match (i32::try_from(a.len()), i32::try_from(b.len())) {
+        (Ok(a_len), Ok(b_len)) => unsafe {
+            CompareStringOrdinal(a.as_ptr(), a_len, b.as_ptr(), b_len, TRUE) == CSTR_EQUAL
+        },
+        _ => false,
     }
 }
 
-impl<T: TagTl32> Iterator for Snapshot<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        let val = T::from_raw(self.current?);
-        if unsafe { T::ITER_NEXT(self.snapshot, self.current.as_mut().unwrap()) == 0 } {
-            self.current = None
+/// Uppercases `s` with the invariant casing table, which `CompareStringOrdinal` folds the case
+/// of both strings with, for keys that match as by [`wide_eq_ignore_case`].
+fn wide_uppercase(s: &[u16]) -> Vec<u16> {
+    const LCMAP_UPPERCASE: u32 = 0x0000_0200;
+    // `LOCALE_NAME_INVARIANT`
+    const INVARIANT: [u16; 1] = [0];
+
+    let len = match i32::try_from(s.len()) {
+        Ok(len) if len > 0 => len,
+        _ => return s.to_vec(),
+    };
+    // uppercasing maps every code unit to exactly one code unit
+    let mut upper = vec![0; s.len()];
+    let written = unsafe {
+        LCMapStringEx(
+            INVARIANT.as_ptr(),
+            LCMAP_UPPERCASE,
+            s.as_ptr(),
+            len,
+            upper.as_mut_ptr(),
+            len,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+        )
+    };
+    match written {
+        0 => s.to_vec(),
+        written => {
+            upper.truncate(written as usize);
+            upper
         }
-        Some(val)
     }
 }
 
-impl<T: TagTl32> FusedIterator for Snapshot<T> {}
+/// Returns the first process whose executable name matches `name`, ignoring case as described
+/// for [`wide_eq_ignore_case`].
+/// The names are compared in place as by [`Snapshot::find_reusing`], only the matching process
+/// is converted into a [`ProcessEntry`].
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn find_process_by_name(name: &str) -> Result<Option<ProcessEntry>> {
+    let name = match U16CString::from_str(name) {
+        Ok(name) => name,
+        // no executable name contains a nul
+        Err(_) => return Ok(None),
+    };
+    Ok(Snapshot::new_process()?.find_reusing(|_, _, _, _, exe| wide_eq_ignore_case(exe, &name)))
+}
 
-impl<T: TagTl32> Drop for Snapshot<T> {
-    fn drop(&mut self) {
-        unsafe { CloseHandle(self.snapshot) };
+/// Repeatedly calls [`find_process_by_name`] every `poll_interval` until a matching process
+/// appears, returning [`None`] if none did within `timeout`.
+/// Every poll takes a fresh [`Snapshot`] which is closed again before sleeping.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let child = tlhelp32::wait_for_process(
+///     "notepad.exe",
+///     Duration::from_secs(5),
+///     Duration::from_millis(50),
+/// )?;
+/// ```
+pub fn wait_for_process(
+    name: &str,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> Result<Option<ProcessEntry>> {
+    let start = Instant::now();
+    loop {
+        if let Some(entry) = find_process_by_name(name)? {
+            return Ok(Some(entry));
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(poll_interval.min(timeout - elapsed));
     }
 }
 
+/// Takes a heap list [`Snapshot`] of the process and returns the entries of all of its heaps as a
+/// single iterator. Every entry still carries the `heap_id` of its heap, so the grouping can be
+/// recovered if needed.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// let total: usize = tlhelp32::heap_entries(pid)?
+///     .map(|entry| entry.block_size)
+///     .sum();
+/// ```
+pub fn heap_entries(pid: u32) -> Result<impl Iterator<Item = HeapEntry>> {
+    Ok(Snapshot::new_heap_list(pid)?.flatten())
+}
+
+/// Returns the heap block of the process that contains `address`, which attributes a pointer to
+/// the allocation it points into. The heaps are walked until the first match, as the process can
+/// have a huge number of blocks.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn heap_block_for_address(pid: u32, address: usize) -> Result<Option<HeapEntry>> {
+    Ok(heap_entries(pid)?.find(|entry| entry.contains(address)))
+}
+
+/// Returns the heap blocks of the process whose `block_size` lies within `min..=max`, which is
+/// useful for hunting down leaks of suspiciously large or numerous allocations.
+/// Free blocks, those with the `LF32_FREE` flag, are left out unless `include_free` is set.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to create a [`Snapshot`]
+pub fn heap_entries_in_size_range(
+    pid: u32,
+    min: usize,
+    max: usize,
+    include_free: bool,
+) -> Result<Vec<HeapEntry>> {
+    Ok(heap_entries(pid)?
+        .filter(|entry| include_free || entry.flags & LF32_FREE == 0)
+        .filter(|entry| (min..=max).contains(&entry.block_size))
+        .collect())
+}
+
 unsafe impl Send for ModuleEntry {}
 unsafe impl Sync for ModuleEntry {}
 unsafe impl Send for HeapList {}
 unsafe impl Send for HeapEntry {}
 unsafe impl Sync for HeapEntry {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exactly_one_main_module() {
+        let modules: Vec<_> = Snapshot::new_module(CURRENT_PROCESS).unwrap().collect();
+        assert!(modules[0].is_main_module());
+        assert_eq!(modules.iter().filter(|m| m.is_main_module()).count(), 1);
+    }
+
+    /// Two pages of the calling process, the second of which is inaccessible.
+    struct GuardedPage(*mut u8);
+
+    impl GuardedPage {
+        fn new() -> Self {
+            use winapi::um::{
+                memoryapi::{VirtualAlloc, VirtualProtect},
+                winnt::{MEM_COMMIT, MEM_RESERVE, PAGE_NOACCESS, PAGE_READWRITE},
+            };
+
+            let pages = unsafe {
+                VirtualAlloc(
+                    ptr::null_mut(),
+                    0x2000,
+                    MEM_COMMIT | MEM_RESERVE,
+                    PAGE_READWRITE,
+                )
+            } as *mut u8;
+            assert!(!pages.is_null());
+            let mut old = 0;
+            let protected =
+                unsafe { VirtualProtect(pages.add(0x1000) as _, 0x1000, PAGE_NOACCESS, &mut old) };
+            assert_ne!(protected, 0);
+            GuardedPage(pages)
+        }
+
+        /// Places `bytes` at the very end of the accessible page and returns their address.
+        fn place_at_end(&self, bytes: &[u8]) -> usize {
+            let at = unsafe { self.0.add(0x1000 - bytes.len()) };
+            unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), at, bytes.len()) };
+            at as usize
+        }
+    }
+
+    impl Drop for GuardedPage {
+        fn drop(&mut self) {
+            use winapi::um::{memoryapi::VirtualFree, winnt::MEM_RELEASE};
+
+            unsafe { VirtualFree(self.0 as _, 0, MEM_RELEASE) };
+        }
+    }
+
+    #[test]
+    fn cstring_ending_at_a_page_boundary() {
+        let page = GuardedPage::new();
+        let pid = unsafe { GetCurrentProcessId() };
+        let address = page.place_at_end(b"hello\0");
+        for &chunk in &[1, 4, DEFAULT_CSTRING_CHUNK] {
+            let string = read_process_cstring(pid, address, chunk).unwrap();
+            assert_eq!(string.as_bytes(), b"hello");
+        }
+    }
+
+    #[test]
+    fn cstring_running_into_an_unreadable_page() {
+        let page = GuardedPage::new();
+        let pid = unsafe { GetCurrentProcessId() };
+        let address = page.place_at_end(b"unterminated");
+        for &chunk in &[1, 5, DEFAULT_CSTRING_CHUNK] {
+            assert!(read_process_cstring(pid, address, chunk).is_err());
+        }
+    }
+
+    #[test]
+    fn cstring_starting_on_an_unreadable_page() {
+        let page = GuardedPage::new();
+        let pid = unsafe { GetCurrentProcessId() };
+        let address = page.0 as usize + 0x1000;
+        for &chunk in &[1, 5, DEFAULT_CSTRING_CHUNK] {
+            let err = read_process_cstring(pid, address, chunk).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn cloned_heap_list_iterates_independently() {
+        use winapi::um::heapapi::{HeapAlloc, HeapCreate, HeapDestroy};
+
+        // a private heap, so the allocations of the test don't change it while it's walked
+        let heap = unsafe { HeapCreate(0, 0, 0) };
+        assert!(!heap.is_null());
+        for size in 1..=8 {
+            assert!(!unsafe { HeapAlloc(heap, 0, size * 16) }.is_null());
+        }
+
+        let mut heap_list = Snapshot::new_heap_list_self()
+            .unwrap()
+            .find(|heap_list| heap_list.heap_id == heap as usize)
+            .unwrap();
+        heap_list.nth(1).unwrap();
+        let clone = heap_list.clone();
+        let mut original_entries = Vec::with_capacity(64);
+        original_entries.extend(heap_list.map(|entry| (entry.address, entry.block_size)));
+        let mut clone_entries = Vec::with_capacity(64);
+        clone_entries.extend(clone.map(|entry| (entry.address, entry.block_size)));
+        assert!(!original_entries.is_empty());
+        assert_eq!(original_entries, clone_entries);
+
+        assert_ne!(unsafe { HeapDestroy(heap) }, 0);
+    }
+
+    fn eq_ignore_case(a: &str, b: &str) -> bool {
+        wide_eq_ignore_case(
+            &U16CString::from_str(a).unwrap(),
+            &U16CString::from_str(b).unwrap(),
+        )
+    }
+
+    #[test]
+    fn eq_ignore_case_folds_invariant_case() {
+        assert!(eq_ignore_case("NOTEPAD.EXE", "notepad.exe"));
+        assert!(eq_ignore_case("É", "é"));
+        assert!(!eq_ignore_case("notepad.exe", "notepad.ex"));
+        assert!(!eq_ignore_case("e", "é"));
+    }
+
+    #[test]
+    fn eq_ignore_case_compares_surrogate_pairs_as_they_are() {
+        assert!(eq_ignore_case("🦀.exe", "🦀.EXE"));
+        assert!(!eq_ignore_case("🦀.exe", "🐍.exe"));
+        // the deseret letters have case mappings outside of the basic multilingual plane, which
+        // aren't applied to surrogate pairs
+        assert!(!eq_ignore_case("\u{10400}", "\u{10428}"));
+    }
+
+    #[test]
+    fn eq_ignore_case_ignores_turkish_casing() {
+        assert!(!eq_ignore_case("İ", "i"));
+        assert!(!eq_ignore_case("ı", "i"));
+        assert!(!eq_ignore_case("ı", "I"));
+        assert!(eq_ignore_case("İ", "İ"));
+    }
+
+    #[test]
+    fn index_by_name_matches_like_eq_ignore_case() {
+        let process_named = |name: &str| {
+            let mut raw: PROCESSENTRY32W = unsafe { mem::zeroed() };
+            copy_wide(&mut raw.szExeFile, &U16CString::from_str(name).unwrap());
+            ProcessEntry::from_raw(raw)
+        };
+        let names = [
+            "NOTEPAD.EXE",
+            "notepad.exe",
+            "notepad.ex",
+            "É",
+            "é",
+            "e",
+            "🦀.exe",
+            "🦀.EXE",
+            "İ",
+            "ı",
+            "i",
+            "I",
+        ];
+        let index =
+            Snapshot::mock(names.iter().map(|name| process_named(name)).collect()).index_by_name();
+        for a in &names {
+            let found: Vec<_> = index
+                .find_all(a)
+                .map(|entry| entry.sz_exe_file.to_string_lossy())
+                .collect();
+            let expected: Vec<_> = names
+                .iter()
+                .filter(|b| eq_ignore_case(a, b))
+                .map(|b| b.to_string())
+                .collect();
+            assert_eq!(found, expected, "{}", a);
+        }
+    }
+
+    /// Counts the allocations of the current thread, so the tests running in parallel don't
+    /// disturb each other.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            // the thread local is gone while the thread shuts down
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations_during<R>(f: impl FnOnce() -> R) -> (usize, R) {
+        let before = ALLOCATIONS.with(|count| count.get());
+        let res = f();
+        (ALLOCATIONS.with(|count| count.get()) - before, res)
+    }
+
+    #[test]
+    fn find_by_name_compares_without_allocating() {
+        let name = U16CString::from_str("no such process.exe").unwrap();
+
+        let snapshot = Snapshot::new_process().unwrap();
+        let (allocations, found) = allocations_during(|| {
+            snapshot.find_reusing(|_, _, _, _, exe| wide_eq_ignore_case(exe, &name))
+        });
+        assert!(found.is_none());
+        assert_eq!(allocations, 0);
+
+        // converting every entry, as plain iteration does, allocates for each of them
+        let mut snapshot = Snapshot::new_process().unwrap();
+        let (allocations, found) = allocations_during(|| {
+            snapshot.find(|entry| wide_eq_ignore_case(&entry.sz_exe_file, &name))
+        });
+        assert!(found.is_none());
+        assert!(allocations > 0);
+    }
+
+    #[test]
+    fn process_entry_equality_is_keyed_on_the_process_id() {
+        let entries: Vec<_> = Snapshot::new_process().unwrap().take(2).collect();
+        let (a, b) = (&entries[0], &entries[1]);
+        assert_eq!(*a, a.process_id);
+        assert_eq!(a.process_id, *a);
+        assert_ne!(*a, b.process_id);
+        assert_ne!(b.process_id, *a);
+
+        let mut renamed = a.clone();
+        renamed.sz_exe_file = U16CString::from_str("renamed.exe").unwrap();
+        assert_eq!(*a, renamed);
+        let set: HashSet<_> = vec![a.clone(), renamed, b.clone()].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    fn mock_threads(count: u32) -> Snapshot<ThreadEntry> {
+        Snapshot::mock(
+            (0..count)
+                .map(|thread_id| ThreadEntry {
+                    thread_id,
+                    owner_process_id: 1,
+                    base_pri: 8,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn nth_matches_repeated_next() {
+        let mut stepped = mock_threads(10);
+        let sixth = (0..6).filter_map(|_| stepped.next()).last();
+        let mut skipped = mock_threads(10);
+        assert_eq!(
+            skipped.nth(5).map(|t| t.thread_id),
+            sixth.map(|t| t.thread_id)
+        );
+        assert_eq!(
+            skipped.next().map(|t| t.thread_id),
+            stepped.next().map(|t| t.thread_id)
+        );
+    }
+
+    #[test]
+    fn nth_past_the_end_fuses() {
+        let mut snapshot = mock_threads(3);
+        assert!(snapshot.nth(5).is_none());
+        assert!(snapshot.next().is_none());
+        assert!(snapshot.nth(1).is_none());
+    }
+
+    #[test]
+    fn mock_yields_its_raw_entries() {
+        let raw: Vec<_> = mock_threads(3)
+            .raw_entries()
+            .map(|raw| (raw.th32ThreadID, raw.th32OwnerProcessID, raw.tpBasePri))
+            .collect();
+        assert_eq!(raw, [(0, 1, 8), (1, 1, 8), (2, 1, 8)]);
+    }
+
+    #[test]
+    fn mock_marks_the_main_module() {
+        let modules: Vec<_> = Snapshot::<ModuleEntry>::new_module(CURRENT_PROCESS)
+            .unwrap()
+            .raw_entries()
+            .map(ModuleEntry::from_windows)
+            .collect();
+        assert!(modules.iter().all(|m| !m.is_main_module()));
+        let names: Vec<_> = modules.iter().map(|m| m.sz_module.clone()).collect();
+
+        let mocked: Vec<_> = Snapshot::mock(modules).collect();
+        assert!(mocked[0].is_main_module());
+        assert_eq!(mocked.iter().filter(|m| m.is_main_module()).count(), 1);
+        let mocked_names: Vec<_> = mocked.iter().map(|m| m.sz_module.clone()).collect();
+        assert_eq!(mocked_names, names);
+    }
+
+    #[test]
+    fn nth_matches_repeated_next_on_a_live_snapshot() {
+        // the views of a combined snapshot walk the same entries every time
+        let mut snapshot = CombinedSnapshot::new(CURRENT_PROCESS).unwrap();
+        let stepped: Vec<_> = snapshot.view::<ThreadEntry>().take(6).collect();
+        let mut view = snapshot.view::<ThreadEntry>();
+        let skipped = view.nth(5);
+        assert_eq!(
+            skipped.map(|t| t.thread_id),
+            stepped.get(5).map(|t| t.thread_id)
+        );
+
+        let count = snapshot.view::<ThreadEntry>().count();
+        let mut view = snapshot.view::<ThreadEntry>();
+        assert!(view.nth(count).is_none());
+        assert!(view.next().is_none());
+    }
+}
@@ -0,0 +1,82 @@
+//! Declarations for the few `ntdll` functions that winapi doesn't provide.
+
+use winapi::shared::{
+    minwindef::ULONG,
+    ntdef::{NTSTATUS, PVOID},
+};
+use winapi::um::winnt::HANDLE;
+
+use std::io::{Error, Result};
+
+pub(crate) const PROCESS_BASIC_INFORMATION: u32 = 0;
+#[cfg(target_pointer_width = "64")]
+pub(crate) const PROCESS_WOW64_INFORMATION: u32 = 26;
+pub(crate) const THREAD_QUERY_SET_WIN32_START_ADDRESS: u32 = 9;
+
+#[repr(C)]
+pub(crate) struct ProcessBasicInformation {
+    pub exit_status: NTSTATUS,
+    pub peb_base_address: usize,
+    pub affinity_mask: usize,
+    pub base_priority: i32,
+    pub unique_process_id: usize,
+    pub inherited_from_unique_process_id: usize,
+}
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtQueryInformationProcess(
+        process_handle: HANDLE,
+        process_information_class: u32,
+        process_information: PVOID,
+        process_information_length: ULONG,
+        return_length: *mut ULONG,
+    ) -> NTSTATUS;
+
+    fn NtQueryInformationThread(
+        thread_handle: HANDLE,
+        thread_information_class: u32,
+        thread_information: PVOID,
+        thread_information_length: ULONG,
+        return_length: *mut ULONG,
+    ) -> NTSTATUS;
+
+    fn RtlNtStatusToDosError(status: NTSTATUS) -> ULONG;
+}
+
+/// Converts a failed `NTSTATUS` into the corresponding os error.
+pub(crate) fn nt_result(status: NTSTATUS) -> Result<()> {
+    if status >= 0 {
+        Ok(())
+    } else {
+        Err(Error::from_raw_os_error(
+            unsafe { RtlNtStatusToDosError(status) } as i32,
+        ))
+    }
+}
+
+/// Queries a fixed size piece of information about a process.
+/// # Safety
+/// `T` has to match the layout the given information class writes.
+pub(crate) unsafe fn query_process<T>(handle: HANDLE, class: u32, info: &mut T) -> Result<()> {
+    nt_result(NtQueryInformationProcess(
+        handle,
+        class,
+        info as *mut T as PVOID,
+        std::mem::size_of::<T>() as ULONG,
+        std::ptr::null_mut(),
+    ))
+}
+
+/// Queries a fixed size piece of information about a thread.
+/// # Safety
+/// `T` has to match the layout the given information class writes.
+pub(crate) unsafe fn query_thread<T>(handle: HANDLE, class: u32, info: &mut T) -> Result<()> {
+    nt_result(NtQueryInformationThread(
+        handle,
+        class,
+        info as *mut T as PVOID,
+        std::mem::size_of::<T>() as ULONG,
+        std::ptr::null_mut(),
+    ))
+}
@@ -0,0 +1,290 @@
+//! Minimal parsing of PE headers, just enough to compare and classify images, list their sections
+//! and resolve exports.
+//! The offsets used here are those of the `IMAGE_*` structures, which are shared between the
+//! 32- and 64-bit formats up to the optional header.
+
+use crate::ProcessReader;
+
+use std::{
+    cmp::Ordering,
+    io::{Error, ErrorKind, Result},
+};
+
+/// The number of bytes read from the start of an image to parse its headers, one page.
+pub(crate) const HEADER_SIZE: usize = 0x1000;
+
+const SECTION_HEADER_SIZE: usize = 40;
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const EXPORT_DIRECTORY_SIZE: usize = 40;
+const DATA_DIRECTORY_SIZE: usize = 8;
+const EXPORT_DIRECTORY: usize = 0;
+const COM_DESCRIPTOR_DIRECTORY: usize = 14;
+const IMAGE_FILE_EXECUTABLE_IMAGE: u16 = 0x0002;
+const IMAGE_FILE_DLL: u16 = 0x2000;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// The machine type a PE image was built for.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Machine {
+    X86,
+    X64,
+    Arm,
+    Arm64,
+    /// Any other `IMAGE_FILE_MACHINE_*` value.
+    Other(u16),
+}
+
+impl From<u16> for Machine {
+    fn from(machine: u16) -> Self {
+        match machine {
+            0x014c => Machine::X86,
+            0x8664 => Machine::X64,
+            0x01c4 => Machine::Arm,
+            0xaa64 => Machine::Arm64,
+            other => Machine::Other(other),
+        }
+    }
+}
+
+/// Classification data read from the PE headers of a loaded module, see
+/// [`ModuleEntry::pe_info`](crate::ModuleEntry::pe_info).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PeInfo {
+    /// The image is a DLL, `IMAGE_FILE_DLL` is set.
+    pub is_dll: bool,
+    /// The image is valid and can be run, `IMAGE_FILE_EXECUTABLE_IMAGE` is set. This is the case
+    /// for DLLs as well.
+    pub is_executable: bool,
+    /// The machine type the image was built for.
+    pub machine: Machine,
+    /// The image has a COM descriptor directory, which makes it a .NET assembly.
+    pub is_managed: bool,
+}
+
+/// A section of a loaded module as described by its `IMAGE_SECTION_HEADER`, see
+/// [`ModuleEntry::sections`](crate::ModuleEntry::sections).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PeSection {
+    /// The name of the section, like `.text`, converted lossily. Names are at most 8 bytes long.
+    pub name: String,
+    /// The absolute address the section is loaded at.
+    pub address: usize,
+    /// The size of the section in memory.
+    pub virtual_size: u32,
+    /// The `IMAGE_SCN_*` flags of the section.
+    pub characteristics: u32,
+}
+
+impl PeSection {
+    /// Checks whether the section is mapped readable, `IMAGE_SCN_MEM_READ` is set.
+    pub fn is_readable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_READ != 0
+    }
+
+    /// Checks whether the section is mapped writable, `IMAGE_SCN_MEM_WRITE` is set.
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_WRITE != 0
+    }
+
+    /// Checks whether the section is mapped executable, `IMAGE_SCN_MEM_EXECUTE` is set.
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+    }
+}
+
+/// The parsed headers of a PE image.
+pub(crate) struct Headers<'a> {
+    pub(crate) machine: u16,
+    pub(crate) characteristics: u16,
+    pub(crate) entry_point: u32,
+    pub(crate) size_of_image: u32,
+    data_directories: &'a [u8],
+    section_headers: &'a [u8],
+}
+
+impl<'a> Headers<'a> {
+    /// Parses the headers at the start of `image`, which has to contain the section table.
+    pub(crate) fn parse(image: &'a [u8]) -> Result<Self> {
+        if image.get(..2) != Some(b"MZ") {
+            return Err(invalid("missing DOS signature"));
+        }
+        let nt = read_u32(image, 0x3c)? as usize;
+        if bytes(image, nt, 4)? != b"PE\0\0" {
+            return Err(invalid("missing NT signature"));
+        }
+        let file_header = nt + 4;
+        let machine = read_u16(image, file_header)?;
+        let characteristics = read_u16(image, file_header + 18)?;
+        let num_sections = read_u16(image, file_header + 2)? as usize;
+        let optional_header_size = read_u16(image, file_header + 16)? as usize;
+        let optional_header = file_header + 20;
+        let entry_point = read_u32(image, optional_header + 16)?;
+        let size_of_image = read_u32(image, optional_header + 56)?;
+        // `NumberOfRvaAndSizes` and the data directories following it come after the differently
+        // sized fields of the optional header
+        let num_data_directories = match read_u16(image, optional_header)? {
+            PE32_MAGIC => optional_header + 92,
+            PE32_PLUS_MAGIC => optional_header + 108,
+            _ => return Err(invalid("unknown optional header magic")),
+        };
+        // there are never more than 16 directories, whatever the header claims
+        let count = (read_u32(image, num_data_directories)? as usize).min(16);
+        let data_directories = bytes(image, num_data_directories + 4, count * DATA_DIRECTORY_SIZE)?;
+        let sections = optional_header + optional_header_size;
+        let section_headers = bytes(image, sections, num_sections * SECTION_HEADER_SIZE)?;
+        Ok(Headers {
+            machine,
+            characteristics,
+            entry_point,
+            size_of_image,
+            data_directories,
+            section_headers,
+        })
+    }
+
+    /// The rva and size of the data directory at `index`, if the image has it.
+    pub(crate) fn data_directory(&self, index: usize) -> Option<(u32, u32)> {
+        let offset = index * DATA_DIRECTORY_SIZE;
+        let rva = read_u32(self.data_directories, offset).ok()?;
+        let size = read_u32(self.data_directories, offset + 4).ok()?;
+        Some((rva, size)).filter(|&(rva, _)| rva != 0)
+    }
+
+    pub(crate) fn info(&self) -> PeInfo {
+        PeInfo {
+            is_dll: self.characteristics & IMAGE_FILE_DLL != 0,
+            is_executable: self.characteristics & IMAGE_FILE_EXECUTABLE_IMAGE != 0,
+            machine: self.machine.into(),
+            is_managed: self.data_directory(COM_DESCRIPTOR_DIRECTORY).is_some(),
+        }
+    }
+
+    /// The raw `IMAGE_SECTION_HEADER`s of the image. These aren't touched by relocations.
+    pub(crate) fn section_headers(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.section_headers.chunks_exact(SECTION_HEADER_SIZE)
+    }
+
+    /// The sections of the image loaded at `base`, which has to span `size` bytes.
+    pub(crate) fn sections(&self, base: usize, size: usize) -> Result<Vec<PeSection>> {
+        self.section_headers()
+            .map(|header| {
+                let name = &header[..8];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(8)];
+                let virtual_size = read_u32(header, 8)?;
+                let rva = read_u32(header, 12)?;
+                match (rva as usize).checked_add(virtual_size as usize) {
+                    Some(end) if end <= size => (),
+                    _ => return Err(invalid("section outside of the image")),
+                }
+                Ok(PeSection {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    address: base + rva as usize,
+                    virtual_size,
+                    characteristics: read_u32(header, 36)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads the headers of the image loaded at `base` in the process of `reader`.
+pub(crate) fn read_headers(reader: &ProcessReader, base: usize) -> Result<Vec<u8>> {
+    let mut header = vec![0; HEADER_SIZE];
+    let len = reader.read(base as _, &mut header)?;
+    header.truncate(len);
+    Ok(header)
+}
+
+/// Resolves the export `name` of the image loaded at `base` in the process of `reader` to its
+/// absolute address. Returns [`None`] if there is no such export or if it is forwarded to
+/// another module.
+pub(crate) fn find_export(
+    reader: &ProcessReader,
+    base: usize,
+    name: &str,
+) -> Result<Option<usize>> {
+    let header = read_headers(reader, base)?;
+    let headers = Headers::parse(&header)?;
+    let (dir_rva, dir_size) = match headers.data_directory(EXPORT_DIRECTORY) {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    let at = |rva: u32| base.wrapping_add(rva as usize) as *const _;
+    // the counts come from the remote image, the tables they size are part of the export data
+    // and have to fit inside the directory as well as the image
+    let table = |rva: u32, count: usize, entry_size: usize| {
+        count
+            .checked_mul(entry_size)
+            .filter(|&len| len <= dir_size as usize)
+            .and_then(|len| (rva as usize).checked_add(len))
+            .filter(|&end| end <= headers.size_of_image as usize)
+            .map(|_| at(rva))
+            .ok_or_else(|| invalid("export table outside of the image"))
+    };
+
+    let mut dir = [0; EXPORT_DIRECTORY_SIZE];
+    reader.read_exact(at(dir_rva), &mut dir)?;
+    let num_functions = read_u32(&dir, 20)? as usize;
+    let num_names = read_u32(&dir, 24)? as usize;
+    let (functions, names, ordinals): (Vec<u32>, Vec<u32>, Vec<u16>) = unsafe {
+        (
+            reader.read_array(table(read_u32(&dir, 28)?, num_functions, 4)?, num_functions)?,
+            reader.read_array(table(read_u32(&dir, 32)?, num_names, 4)?, num_names)?,
+            reader.read_array(table(read_u32(&dir, 36)?, num_names, 2)?, num_names)?,
+        )
+    };
+
+    // the name table is sorted, and comparing just as many bytes as the wanted name has plus its
+    // terminator orders the names the same way `strcmp` does
+    let wanted: Vec<u8> = name.bytes().chain(Some(0)).collect();
+    let mut candidate = vec![0; wanted.len()];
+    let (mut lo, mut hi) = (0, names.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let len = reader.read(at(names[mid]), &mut candidate)?;
+        match candidate[..len].cmp(&wanted) {
+            Ordering::Less => lo = mid + 1,
+            Ordering::Greater => hi = mid,
+            Ordering::Equal => {
+                let rva = ordinals
+                    .get(mid)
+                    .and_then(|&ordinal| functions.get(ordinal as usize))
+                    .copied()
+                    .ok_or_else(|| invalid("export ordinal out of bounds"))?;
+                // forwarded exports point at a forwarder string inside the export directory
+                let forwarded = (dir_rva..dir_rva.saturating_add(dir_size)).contains(&rva);
+                return Ok(if forwarded {
+                    None
+                } else {
+                    Some(at(rva) as usize)
+                });
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid PE image: {}", msg))
+}
+
+fn bytes(image: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| image.get(offset..end))
+        .ok_or_else(|| invalid("truncated headers"))
+}
+
+fn read_u16(image: &[u8], offset: usize) -> Result<u16> {
+    let b = bytes(image, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(image: &[u8], offset: usize) -> Result<u32> {
+    let b = bytes(image, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
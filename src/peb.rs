@@ -0,0 +1,243 @@
+//! Locating and reading the `RTL_USER_PROCESS_PARAMETERS` of another process through its PEB.
+//! The offsets used here are those of the structures as laid out by 32- and 64-bit Windows.
+
+use crate::{ntdll, ProcessReader};
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    mem,
+};
+
+struct Layout {
+    ptr_size: usize,
+    process_parameters: usize,
+    current_directory: usize,
+    command_line: usize,
+    environment: usize,
+    environment_size: usize,
+}
+
+/// The largest environment block that is read, 32 MiB. Windows limits an environment block to
+/// 32767 characters per variable and has no overall limit, but anything larger than this is
+/// certainly corrupted.
+const MAX_ENVIRONMENT_SIZE: usize = 32 * 1024 * 1024;
+
+#[cfg(target_pointer_width = "64")]
+const LAYOUT_64: Layout = Layout {
+    ptr_size: 8,
+    process_parameters: 0x20,
+    current_directory: 0x38,
+    command_line: 0x70,
+    environment: 0x80,
+    environment_size: 0x3f0,
+};
+
+const LAYOUT_32: Layout = Layout {
+    ptr_size: 4,
+    process_parameters: 0x10,
+    current_directory: 0x24,
+    command_line: 0x40,
+    environment: 0x48,
+    environment_size: 0x290,
+};
+
+/// Returns the address of the PEB the process's user mode code actually uses, together with the
+/// layout it has. For WOW64 processes this is the 32-bit PEB.
+#[cfg(target_pointer_width = "64")]
+fn locate_peb(reader: &ProcessReader) -> Result<(usize, &'static Layout)> {
+    let mut wow64_peb = 0usize;
+    unsafe {
+        ntdll::query_process(
+            reader.handle(),
+            ntdll::PROCESS_WOW64_INFORMATION,
+            &mut wow64_peb,
+        )?
+    };
+    if wow64_peb != 0 {
+        return Ok((wow64_peb, &LAYOUT_32));
+    }
+    let mut info: ntdll::ProcessBasicInformation = unsafe { mem::zeroed() };
+    unsafe { ntdll::query_process(reader.handle(), ntdll::PROCESS_BASIC_INFORMATION, &mut info)? };
+    Ok((info.peb_base_address, &LAYOUT_64))
+}
+
+#[cfg(target_pointer_width = "32")]
+fn locate_peb(reader: &ProcessReader) -> Result<(usize, &'static Layout)> {
+    use crate::is_wow64;
+    use winapi::um::processthreadsapi::GetCurrentProcess;
+
+    if is_wow64(unsafe { GetCurrentProcess() })? && !is_wow64(reader.handle())? {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "cannot read the PEB of a 64-bit process from a 32-bit process",
+        ));
+    }
+    let mut info: ntdll::ProcessBasicInformation = unsafe { mem::zeroed() };
+    unsafe { ntdll::query_process(reader.handle(), ntdll::PROCESS_BASIC_INFORMATION, &mut info)? };
+    Ok((info.peb_base_address, &LAYOUT_32))
+}
+
+/// The remote `RTL_USER_PROCESS_PARAMETERS` block of a process.
+pub(crate) struct ProcessParameters<'r> {
+    reader: &'r ProcessReader,
+    address: usize,
+    layout: &'static Layout,
+}
+
+impl<'r> ProcessParameters<'r> {
+    pub(crate) fn locate(reader: &'r ProcessReader) -> Result<Self> {
+        let (peb, layout) = locate_peb(reader)?;
+        let address = read_ptr(reader, layout, peb + layout.process_parameters)?;
+        if address == 0 {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "the process has no process parameters",
+            ));
+        }
+        Ok(ProcessParameters {
+            reader,
+            address,
+            layout,
+        })
+    }
+
+    /// Reads the raw environment block, a sequence of NUL-terminated `KEY=VALUE` strings.
+    pub(crate) fn environment(&self) -> Result<Vec<u16>> {
+        let block = self.read_ptr(self.layout.environment)?;
+        let size = self.read_ptr(self.layout.environment_size)?;
+        if size > MAX_ENVIRONMENT_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "environment size of {} bytes exceeds the limit of {} bytes",
+                    size, MAX_ENVIRONMENT_SIZE
+                ),
+            ));
+        }
+        read_wide(self.reader, block, size)
+    }
+
+    /// Reads the DOS path of the current directory, which keeps its trailing backslash.
+    pub(crate) fn current_directory(&self) -> Result<Vec<u16>> {
+        self.read_unicode_string(self.layout.current_directory)
+    }
+
+    /// Reads the command line the process was started with.
+    pub(crate) fn command_line(&self) -> Result<Vec<u16>> {
+        self.read_unicode_string(self.layout.command_line)
+    }
+
+    fn read_ptr(&self, offset: usize) -> Result<usize> {
+        read_ptr(self.reader, self.layout, self.address + offset)
+    }
+
+    /// Reads the contents of the `UNICODE_STRING` at `offset`, a byte length followed by the
+    /// pointer to the characters at the next pointer aligned offset.
+    fn read_unicode_string(&self, offset: usize) -> Result<Vec<u16>> {
+        let mut len = [0; 2];
+        self.reader
+            .read_exact((self.address + offset) as _, &mut len)?;
+        let len = u16::from_le_bytes(len) as usize;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let buffer = self.read_ptr(offset + self.layout.ptr_size)?;
+        if buffer == 0 || len & 1 != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the process parameters contain a malformed string",
+            ));
+        }
+        read_wide(self.reader, buffer, len)
+    }
+}
+
+fn read_ptr(reader: &ProcessReader, layout: &Layout, address: usize) -> Result<usize> {
+    let mut buf = [0; mem::size_of::<u64>()];
+    reader.read_exact(address as _, &mut buf[..layout.ptr_size])?;
+    Ok(u64::from_le_bytes(buf) as usize)
+}
+
+fn read_wide(reader: &ProcessReader, address: usize, byte_len: usize) -> Result<Vec<u16>> {
+    let mut buf = vec![0; byte_len];
+    reader.read_exact(address as _, &mut buf)?;
+    Ok(buf
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect())
+}
+
+/// Splits an environment block into its `(key, value)` pairs, converting them lossily.
+/// The key is split off at the first `=` after the first character, since the hidden per-drive
+/// variables like `=C:` start with one.
+pub(crate) fn parse_environment(block: &[u16]) -> Vec<(String, String)> {
+    block
+        .split(|&c| c == 0)
+        .take_while(|var| !var.is_empty())
+        .map(|var| {
+            let split = var
+                .iter()
+                .skip(1)
+                .position(|&c| c == u16::from(b'='))
+                .map_or(var.len(), |pos| pos + 1);
+            let value = var.get(split + 1..).unwrap_or_default();
+            (
+                String::from_utf16_lossy(&var[..split]),
+                String::from_utf16_lossy(value),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_environment;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    fn pairs(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_empty_block() {
+        assert!(parse_environment(&[]).is_empty());
+        assert!(parse_environment(&[0, 0]).is_empty());
+    }
+
+    #[test]
+    fn parse_block_without_terminator() {
+        assert_eq!(
+            parse_environment(&wide("A=1\0B=2")),
+            pairs(&[("A", "1"), ("B", "2")])
+        );
+    }
+
+    #[test]
+    fn parse_stops_at_double_nul() {
+        assert_eq!(
+            parse_environment(&wide("A=1\0\0B=2\0\0")),
+            pairs(&[("A", "1")])
+        );
+    }
+
+    #[test]
+    fn parse_drive_variables() {
+        assert_eq!(
+            parse_environment(&wide("=C:=C:\\Windows\0=D:=D:\\\0PATH=C:\\bin\0\0")),
+            pairs(&[("=C:", "C:\\Windows"), ("=D:", "D:\\"), ("PATH", "C:\\bin")])
+        );
+    }
+
+    #[test]
+    fn parse_variable_without_value() {
+        assert_eq!(
+            parse_environment(&wide("EMPTY=\0NOEQ\0\0")),
+            pairs(&[("EMPTY", ""), ("NOEQ", "")])
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use winapi::shared::minwindef::{LPCVOID, LPVOID};
+use winapi::um::{
+    memoryapi::{ReadProcessMemory, VirtualProtectEx, VirtualQueryEx, WriteProcessMemory},
+    minwinbase::STILL_ACTIVE,
+    processthreadsapi::{FlushInstructionCache, GetCurrentProcess, GetExitCodeProcess},
+    winnt::{
+        HANDLE, MEMORY_BASIC_INFORMATION, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+        PAGE_EXECUTE_WRITECOPY, PAGE_READWRITE, PAGE_WRITECOPY, PROCESS_QUERY_INFORMATION,
+        PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE,
+    },
+};
+
+use crate::{
+    is_wow64, open_process, process_creation_time, raw, ProcessGone, ProcessIdentity,
+    ProcessMismatch,
+};
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    mem,
+    ops::Range,
+    os::windows::io::OwnedHandle,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+/// The number of bytes [`ProcessMemory::read_range`] tries to read at once before falling back to
+/// single pages.
+const CHUNK_SIZE: usize = 16 * PAGE_SIZE;
+
+/// The result of [`ProcessMemory::read_range`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RangeRead {
+    /// The address the range starts at.
+    pub start: usize,
+    /// The contents of the whole range, with the bytes of unreadable pages set to zero. Check
+    /// [`unreadable`](Self::unreadable) to tell them apart from memory that holds zeros.
+    pub data: Vec<u8>,
+    /// The readable parts of the range as ascending, non-adjacent address ranges.
+    pub readable: Vec<Range<usize>>,
+    /// The gaps between the readable parts, the unreadable parts of the range as ascending,
+    /// non-adjacent address ranges. This is empty if the whole range could be read.
+    pub unreadable: Vec<Range<usize>>,
+}
+
+impl RangeRead {
+    /// Returns the readable parts of the range together with their contents.
+    pub fn readable_chunks(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.readable.iter().map(move |range| {
+            let offset = range.start - self.start;
+            (range.start, &self.data[offset..offset + range.len()])
+        })
+    }
+}
+
+/// An open handle to another process that its memory can be read through, and if opened
+/// with [`open_writable`](ProcessMemory::open_writable), written to.
+/// Unlike [`read_process_memory`](crate::read_process_memory) this opens the process only once,
+/// which makes it the better choice for reading many values from the same process.
+#[derive(Debug)]
+pub struct ProcessMemory {
+    handle: OwnedHandle,
+    process_id: u32,
+    check_alive: bool,
+    writable: bool,
+}
+
+/// The name [`ProcessMemory`] had before it could be opened for writing. Memory opened with
+/// [`open`](ProcessMemory::open) is still only readable.
+pub type ProcessReader = ProcessMemory;
+
+impl ProcessMemory {
+    /// Opens the process with the given id for reading.
+    /// This requires the `PROCESS_VM_READ` and `PROCESS_QUERY_LIMITED_INFORMATION` access rights.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    pub fn open(process_id: u32) -> Result<Self> {
+        let access = PROCESS_VM_READ | PROCESS_QUERY_LIMITED_INFORMATION;
+        Ok(ProcessMemory {
+            handle: open_process(access, process_id)?,
+            process_id,
+            check_alive: false,
+            writable: false,
+        })
+    }
+
+    /// Opens the process with the given id for reading and writing.
+    /// This requires the `PROCESS_VM_READ`, `PROCESS_VM_WRITE`, `PROCESS_VM_OPERATION` and
+    /// `PROCESS_QUERY_INFORMATION` access rights, which are usually only granted for processes of
+    /// other users if the caller is elevated.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    pub fn open_writable(process_id: u32) -> Result<Self> {
+        let access = PROCESS_VM_READ
+            | PROCESS_VM_WRITE
+            | PROCESS_VM_OPERATION
+            | PROCESS_QUERY_INFORMATION
+            | PROCESS_QUERY_LIMITED_INFORMATION;
+        Ok(ProcessMemory {
+            handle: open_process(access, process_id)?,
+            process_id,
+            check_alive: false,
+            writable: true,
+        })
+    }
+
+    /// Checks whether the memory was opened with [`open_writable`](Self::open_writable).
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Opens the process of `identity` for reading like [`open`](Self::open), then verifies that
+    /// it is the very process the identity was captured from and not one that reused its id.
+    /// Reads through the returned reader are guaranteed to target that process, as the handle
+    /// keeps referring to it even if it exits.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to open the process
+    /// or to query its creation time, or with a [`ProcessMismatch`] wrapped in an
+    /// [`ErrorKind::NotFound`] error if the process id was reused.
+    pub fn open_verified(identity: &ProcessIdentity) -> Result<Self> {
+        let reader = Self::open(identity.process_id)?;
+        let actual_creation_time = process_creation_time(reader.handle())?;
+        if actual_creation_time != identity.creation_time {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                ProcessMismatch {
+                    expected: *identity,
+                    actual_creation_time,
+                },
+            ));
+        }
+        Ok(reader)
+    }
+
+    /// The id of the process this reader was opened for.
+    pub fn process_id(&self) -> u32 {
+        self.process_id
+    }
+
+    /// Retrieves the windows process handle
+    pub fn handle(&self) -> HANDLE {
+        raw(&self.handle)
+    }
+
+    /// Makes every read check whether the process is still running first, failing with a
+    /// [`ProcessGone`] error once it has exited instead of returning an access denied error.
+    /// This costs an additional `GetExitCodeProcess` call per read, so it's off by default.
+    pub fn check_alive(mut self, check_alive: bool) -> Self {
+        self.check_alive = check_alive;
+        self
+    }
+
+    /// Checks whether the process is still running.
+    /// A process that exited with the code `STILL_ACTIVE` (`259`) is reported to be alive, and if
+    /// its exit code can't be queried it is assumed to have exited.
+    pub fn is_alive(&self) -> bool {
+        let mut exit_code = 0;
+        unsafe {
+            GetExitCodeProcess(self.handle(), &mut exit_code) != 0 && exit_code == STILL_ACTIVE
+        }
+    }
+
+    /// Fails with a [`ProcessGone`] error if [`check_alive`](Self::check_alive) is enabled and
+    /// the process has exited.
+    fn ensure_alive(&self) -> Result<()> {
+        if self.check_alive && !self.is_alive() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                ProcessGone {
+                    process_id: self.process_id,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// Copies memory of the process at the specified address into a supplied slice.
+    /// Returns the number of bytes that were actually read.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the memory can't be read, or
+    /// with a [`ProcessGone`] error if the process has exited and
+    /// [`check_alive`](Self::check_alive) is enabled.
+    // the address is only ever dereferenced in the context of the other process
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn read(&self, address: LPCVOID, buffer: &mut [u8]) -> Result<usize> {
+        unsafe { self.read_raw(address, buffer.as_mut_ptr(), buffer.len()) }
+    }
+
+    /// Like [`read`](Self::read), but into `len` bytes at `buffer`, which don't have to be
+    /// initialized.
+    /// # Safety
+    /// `buffer` has to be valid for writes of `len` bytes.
+    unsafe fn read_raw(&self, address: LPCVOID, buffer: *mut u8, len: usize) -> Result<usize> {
+        self.ensure_alive()?;
+        let mut num_bytes_read = 0;
+        if ReadProcessMemory(
+            self.handle(),
+            address,
+            buffer as *mut _,
+            len,
+            &mut num_bytes_read,
+        ) == 0
+        {
+            Err(Error::last_os_error())
+        } else {
+            Ok(num_bytes_read)
+        }
+    }
+
+    /// Writes `data` into the memory of the process at the specified address and returns the
+    /// number of bytes that were actually written. The memory has to be opened with
+    /// [`open_writable`](Self::open_writable).
+    ///
+    /// Pages that aren't writable, like the code and read-only data of modules, are made writable
+    /// with `VirtualProtectEx` for the duration of the write, and their protection is restored
+    /// afterwards, whether the write succeeded or not. Executable pages stay executable while they
+    /// are written and the instruction cache is flushed for them, so patching code is safe
+    /// as long as no thread executes the patched instructions at the same time.
+    /// Guard pages whose protection is writable otherwise are written as they are, without
+    /// clearing `PAGE_GUARD` first, so the write fires the guard.
+    ///
+    /// The write is split at the boundaries of memory regions, as every region has its own
+    /// protection. Like [`io::Write::write`], once a part of `data` was written, a failure on the
+    /// rest is reported by returning the shorter length rather than the error.
+    ///
+    /// [`io::Write::write`]: std::io::Write::write
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the protection of the memory
+    /// can't be changed or if nothing could be written, with [`ErrorKind::PermissionDenied`] if
+    /// the memory was opened read-only, or with a [`ProcessGone`] error if the process has exited
+    /// and [`check_alive`](Self::check_alive) is enabled. A failure to restore the protection is
+    /// always reported, even if bytes were written, as the memory is left writable then.
+    // the address is only ever dereferenced in the context of the other process
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn write(&self, address: LPVOID, data: &[u8]) -> Result<usize> {
+        if !self.writable {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "the process memory was opened read-only",
+            ));
+        }
+        self.ensure_alive()?;
+        const WRITABLE: u32 =
+            PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+        const EXECUTABLE: u32 =
+            PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+
+        let mut written = 0;
+        // fails the write, unless a part of it was written already
+        macro_rules! fail {
+            ($err:expr) => {
+                if written == 0 {
+                    return Err($err);
+                } else {
+                    break;
+                }
+            };
+        }
+        // every region has a single protection, so the write is split at region boundaries
+        while written < data.len() {
+            let at = (address as usize).wrapping_add(written);
+            let mut info: MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+            let info_size = mem::size_of::<MEMORY_BASIC_INFORMATION>();
+            if unsafe { VirtualQueryEx(self.handle(), at as LPCVOID, &mut info, info_size) } == 0 {
+                fail!(Error::last_os_error());
+            }
+            let region_end = (info.BaseAddress as usize).saturating_add(info.RegionSize);
+            let chunk = &data[written..data.len().min(written + (region_end - at))];
+            let executable = info.Protect & EXECUTABLE != 0;
+
+            let mut old_protect = 0;
+            let reprotect = info.Protect & WRITABLE == 0;
+            if reprotect {
+                let protect = if executable {
+                    PAGE_EXECUTE_READWRITE
+                } else {
+                    PAGE_READWRITE
+                };
+                if unsafe {
+                    VirtualProtectEx(
+                        self.handle(),
+                        at as LPVOID,
+                        chunk.len(),
+                        protect,
+                        &mut old_protect,
+                    )
+                } == 0
+                {
+                    fail!(Error::last_os_error());
+                }
+            }
+            let mut num_bytes_written = 0;
+            let res = unsafe {
+                WriteProcessMemory(
+                    self.handle(),
+                    at as LPVOID,
+                    chunk.as_ptr() as LPCVOID,
+                    chunk.len(),
+                    &mut num_bytes_written,
+                )
+            };
+            // fetch the error before restoring the protection overwrites it
+            let write_err = if res == 0 {
+                Some(Error::last_os_error())
+            } else {
+                None
+            };
+            if reprotect {
+                let mut protect = 0;
+                let restored = unsafe {
+                    VirtualProtectEx(
+                        self.handle(),
+                        at as LPVOID,
+                        chunk.len(),
+                        old_protect,
+                        &mut protect,
+                    )
+                };
+                if restored == 0 && write_err.is_none() {
+                    return Err(Error::last_os_error());
+                }
+            }
+            if executable && num_bytes_written != 0 {
+                unsafe { FlushInstructionCache(self.handle(), at as LPCVOID, num_bytes_written) };
+            }
+            written += num_bytes_written;
+            if let Some(e) = write_err {
+                fail!(e);
+            }
+            if num_bytes_written < chunk.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Like [`read`](Self::read), but fails with [`ErrorKind::UnexpectedEof`] if the supplied
+    /// slice couldn't be filled completely.
+    pub fn read_exact(&self, address: LPCVOID, buffer: &mut [u8]) -> Result<()> {
+        if self.read(address, buffer)? == buffer.len() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "failed to read the whole buffer",
+            ))
+        }
+    }
+
+    /// The size of a pointer in the process, `4` for 32-bit and `8` for 64-bit processes.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if the bitness of the process
+    /// can't be queried, or with [`ErrorKind::Unsupported`] if the process is 64-bit while the
+    /// calling process is 32-bit.
+    pub fn pointer_size(&self) -> Result<usize> {
+        if is_wow64(self.handle())? {
+            Ok(4)
+        } else if cfg!(target_pointer_width = "64") {
+            Ok(8)
+        } else if is_wow64(unsafe { GetCurrentProcess() })? {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "cannot read pointers of a 64-bit process from a 32-bit process",
+            ))
+        } else {
+            // 32-bit windows
+            Ok(4)
+        }
+    }
+
+    /// Reads a pointer sized value at the specified address. The size of the pointer is that of
+    /// the process, not of the calling one, see [`pointer_size`](Self::pointer_size).
+    /// # Errors
+    /// This function fails if the memory couldn't be read completely or if the pointer size can't
+    /// be determined.
+    pub fn read_ptr(&self, address: LPCVOID) -> Result<usize> {
+        self.read_ptr_sized(address, self.pointer_size()?)
+    }
+
+    /// Follows a chain of pointers, as found by memory scanners: starting at `base` the pointer
+    /// at the current address is read and the next offset added to it, for every offset in turn.
+    /// Without offsets `base` itself is returned.
+    /// # Errors
+    /// This function fails if any pointer in the chain couldn't be read or if the pointer size
+    /// can't be determined.
+    ///
+    /// # Usage
+    ///
+    /// ```rust,no_run
+    /// let reader = tlhelp32::ProcessMemory::open(pid)?;
+    /// // [[base] + 0x10] + 0x8
+    /// let health = reader.resolve_chain(base, &[0x10, 0x8])?;
+    /// ```
+    pub fn resolve_chain(&self, base: usize, offsets: &[usize]) -> Result<usize> {
+        let ptr_size = self.pointer_size()?;
+        offsets.iter().try_fold(base, |address, &offset| {
+            Ok(self
+                .read_ptr_sized(address as LPCVOID, ptr_size)?
+                .wrapping_add(offset))
+        })
+    }
+
+    fn read_ptr_sized(&self, address: LPCVOID, ptr_size: usize) -> Result<usize> {
+        let mut buf = [0; mem::size_of::<u64>()];
+        self.read_exact(address, &mut buf[..ptr_size])?;
+        Ok(u64::from_le_bytes(buf) as usize)
+    }
+
+    /// Reads `len` bytes starting at `start` without failing on the first hole: the range is read
+    /// in page aligned chunks, and pages that aren't readable, because they aren't mapped or
+    /// are protected, are zeroed in the returned data and reported in its
+    /// [`unreadable`](RangeRead::unreadable) ranges instead of its
+    /// [`readable`](RangeRead::readable) ones.
+    /// Chunks of several pages are read at once, only those containing a hole are retried page by
+    /// page, which keeps scanning large mostly readable ranges fast.
+    /// # Errors
+    /// This function fails with [`ErrorKind::InvalidInput`] if the range overflows the address
+    /// space.
+    pub fn read_range(&self, start: usize, len: usize) -> Result<RangeRead> {
+        let end = start
+            .checked_add(len)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "range overflows a usize"))?;
+        let mut data = vec![0; len];
+        let mut readable: Vec<Range<usize>> = Vec::new();
+        let mut unreadable: Vec<Range<usize>> = Vec::new();
+        let mark = |ranges: &mut Vec<Range<usize>>, range: Range<usize>| match ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => ranges.push(range),
+        };
+
+        let mut chunk_start = start;
+        while chunk_start < end {
+            // chunks end on chunk aligned addresses, so only the first one may be shorter
+            let chunk_end = ((chunk_start / CHUNK_SIZE).saturating_add(1))
+                .saturating_mul(CHUNK_SIZE)
+                .min(end);
+            let buf = &mut data[chunk_start - start..chunk_end - start];
+            if self.read_exact(chunk_start as LPCVOID, buf).is_ok() {
+                mark(&mut readable, chunk_start..chunk_end);
+            } else {
+                let mut page_start = chunk_start;
+                while page_start < chunk_end {
+                    let page_end = ((page_start / PAGE_SIZE).saturating_add(1))
+                        .saturating_mul(PAGE_SIZE)
+                        .min(chunk_end);
+                    let buf = &mut data[page_start - start..page_end - start];
+                    if self.read_exact(page_start as LPCVOID, buf).is_ok() {
+                        mark(&mut readable, page_start..page_end);
+                    } else {
+                        // the failed read might have written parts of the page
+                        buf.iter_mut().for_each(|b| *b = 0);
+                        mark(&mut unreadable, page_start..page_end);
+                    }
+                    page_start = page_end;
+                }
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(RangeRead {
+            start,
+            data,
+            readable,
+            unreadable,
+        })
+    }
+
+    /// Reads `count` consecutive values of type `T` starting at the specified address.
+    /// # Errors
+    /// This function fails if the memory couldn't be read completely, or with
+    /// [`ErrorKind::InvalidInput`] if the total size of the values overflows a `usize`.
+    /// # Safety
+    /// Any bit pattern read from the process has to be a valid value of `T`.
+    pub unsafe fn read_array<T: Copy>(&self, address: LPCVOID, count: usize) -> Result<Vec<T>> {
+        let byte_len = mem::size_of::<T>()
+            .checked_mul(count)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "array size overflows a usize"))?;
+        let mut values = Vec::<T>::with_capacity(count);
+        // read straight into the spare capacity, it is only exposed once it has been filled
+        if byte_len != 0
+            && self.read_raw(address, values.as_mut_ptr() as *mut u8, byte_len)? != byte_len
+        {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "failed to read the whole buffer",
+            ));
+        }
+        values.set_len(count);
+        Ok(values)
+    }
+}
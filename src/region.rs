@@ -0,0 +1,186 @@
+//! Enumeration of the virtual memory regions of another process with `VirtualQueryEx`, which
+//! toolhelp has no snapshot for.
+
+use crate::{open_process, raw, Snapshot};
+
+use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+use winapi::um::{
+    memoryapi::VirtualQueryEx,
+    winnt::{
+        MEMORY_BASIC_INFORMATION, MEM_COMMIT, PAGE_EXECUTE, PAGE_EXECUTE_READ,
+        PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_NOACCESS, PAGE_READONLY,
+        PAGE_READWRITE, PAGE_WRITECOPY, PROCESS_QUERY_INFORMATION,
+    },
+};
+
+use std::{
+    io::{Error, Result},
+    mem,
+};
+
+/// A region of pages of the virtual address space of a process that share the same state,
+/// protection and type, as described by `MEMORY_BASIC_INFORMATION`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryRegion {
+    /// The address the region starts at.
+    pub base: usize,
+    /// The size of the region in bytes.
+    pub size: usize,
+    /// The base address of the allocation the region belongs to, `0` for free regions.
+    pub allocation_base: usize,
+    /// The state of the pages, one of `MEM_COMMIT`, `MEM_RESERVE` or `MEM_FREE`.
+    pub state: u32,
+    /// The access protection of the pages, one of the `PAGE_*` constants possibly combined with
+    /// modifiers like `PAGE_GUARD`. This is `0` if the pages aren't committed.
+    pub protect: u32,
+    /// The type of the pages, one of `MEM_IMAGE`, `MEM_MAPPED` or `MEM_PRIVATE`, `0` for free
+    /// regions.
+    pub kind: u32,
+}
+
+impl MemoryRegion {
+    /// The address right after the end of the region.
+    pub fn end(&self) -> usize {
+        self.base.wrapping_add(self.size)
+    }
+
+    /// Checks whether the pages of the region are committed and can be read, that is whether
+    /// their protection is one of `PAGE_READONLY`, `PAGE_READWRITE`, `PAGE_WRITECOPY`,
+    /// `PAGE_EXECUTE_READ`, `PAGE_EXECUTE_READWRITE` or `PAGE_EXECUTE_WRITECOPY`.
+    /// Guard pages aren't readable, reading them raises a guard page exception in the process,
+    /// and neither are `PAGE_NOACCESS` and `PAGE_EXECUTE` pages. The `PAGE_NOCACHE` and
+    /// `PAGE_WRITECOMBINE` modifiers don't affect readability.
+    pub fn is_readable(&self) -> bool {
+        self.state == MEM_COMMIT
+            && self.protect & (PAGE_GUARD | PAGE_NOACCESS) == 0
+            && self.protect & READABLE != 0
+    }
+
+    /// Checks whether the pages of the region are committed and can be executed, that is whether
+    /// their protection is one of `PAGE_EXECUTE`, `PAGE_EXECUTE_READ`, `PAGE_EXECUTE_READWRITE`
+    /// or `PAGE_EXECUTE_WRITECOPY`. Guard pages are included, as they turn executable once
+    /// touched.
+    pub fn is_executable(&self) -> bool {
+        self.state == MEM_COMMIT && self.protect & EXECUTABLE != 0
+    }
+}
+
+/// The base protections [`MemoryRegion::is_readable`] considers readable.
+const READABLE: u32 = PAGE_READONLY
+    | PAGE_READWRITE
+    | PAGE_WRITECOPY
+    | PAGE_EXECUTE_READ
+    | PAGE_EXECUTE_READWRITE
+    | PAGE_EXECUTE_WRITECOPY;
+
+/// The base protections [`MemoryRegion::is_executable`] considers executable.
+const EXECUTABLE: u32 =
+    PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+
+/// Walks the virtual address space of the process from address `0` upward and returns all of
+/// its regions, free and reserved ones included. Memory scanners can use this to restrict
+/// themselves to committed regions before reading them with a [`ProcessReader`].
+/// This opens the process with the `PROCESS_QUERY_INFORMATION` access right.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to open the process
+/// or if querying a region fails for any other reason than the end of the address space.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// use winapi::um::winnt::MEM_COMMIT;
+///
+/// let committed: usize = tlhelp32::memory_regions(pid)?
+///     .iter()
+///     .filter(|region| region.state == MEM_COMMIT)
+///     .map(|region| region.size)
+///     .sum();
+/// ```
+///
+/// [`ProcessReader`]: crate::ProcessReader
+pub fn memory_regions(pid: u32) -> Result<Vec<MemoryRegion>> {
+    let process = open_process(PROCESS_QUERY_INFORMATION, pid)?;
+    let mut regions = Vec::new();
+    let mut address = 0usize;
+    loop {
+        let mut info: MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+        let len = unsafe {
+            VirtualQueryEx(
+                raw(&process),
+                address as *const _,
+                &mut info,
+                mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+        if len == 0 {
+            let err = Error::last_os_error();
+            // addresses past the highest user mode address are rejected as invalid
+            if err.raw_os_error() == Some(ERROR_INVALID_PARAMETER as i32) {
+                break;
+            }
+            return Err(err);
+        }
+        let region = MemoryRegion {
+            base: info.BaseAddress as usize,
+            size: info.RegionSize,
+            allocation_base: info.AllocationBase as usize,
+            state: info.State,
+            protect: info.Protect,
+            kind: info.Type,
+        };
+        regions.push(region);
+        // the last region might end exactly at the top of the address space
+        match region.base.checked_add(region.size) {
+            Some(next) if next > address => address = next,
+            _ => break,
+        }
+    }
+    Ok(regions)
+}
+
+/// Like [`memory_regions`], but returns only the regions that are committed and readable, as
+/// determined by [`MemoryRegion::is_readable`]. These are the regions worth feeding into a
+/// [`ProcessReader`](crate::ProcessReader) when scanning the memory of a process.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to open the process
+/// or if querying a region fails for any other reason than the end of the address space.
+pub fn readable_regions(pid: u32) -> Result<Vec<MemoryRegion>> {
+    let mut regions = memory_regions(pid)?;
+    regions.retain(MemoryRegion::is_readable);
+    Ok(regions)
+}
+
+/// Returns the committed, executable regions of the process that don't lie within any of its
+/// loaded modules. Code outside of any module is a strong indicator of injected code or
+/// shellcode, as legitimately loaded code is mapped from an image file and shows up in the
+/// module [`Snapshot`].
+///
+/// There are legitimate sources of such code though: JIT compilers, like those of .NET, Java and
+/// JavaScript engines, generate code into private executable memory, as do some hooking and
+/// protection libraries with their trampolines. Interpreting the result requires knowing what
+/// runs in the process.
+/// # Errors
+/// This function fails and returns the appropriate os error if it is unable to enumerate the
+/// regions as by [`memory_regions`] or to create a module [`Snapshot`] of the process
+pub fn unbacked_executable_regions(pid: u32) -> Result<Vec<MemoryRegion>> {
+    let regions = memory_regions(pid)?;
+    let mut modules: Vec<(usize, usize)> = Snapshot::new_module(pid)?
+        .map(|module| {
+            let base = module.base_addr as usize;
+            (base, base.saturating_add(module.base_size as usize))
+        })
+        .collect();
+    modules.sort_unstable();
+    Ok(regions
+        .into_iter()
+        .filter(MemoryRegion::is_executable)
+        .filter(|region| {
+            // the module starting last at or before the region is the only one that can contain it
+            let candidates = modules.partition_point(|&(base, _)| base <= region.base);
+            match candidates.checked_sub(1).map(|i| modules[i]) {
+                Some((_, end)) => region.end() > end,
+                None => true,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,162 @@
+//! Polling the process list in the background and reporting processes starting and exiting.
+
+use crate::{ProcessEntry, Snapshot};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Result,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+type OnStarted = Box<dyn FnMut(ProcessEntry) + Send>;
+type OnExited = Box<dyn FnMut(u32) + Send>;
+
+#[derive(Default)]
+struct Callbacks {
+    on_started: Option<OnStarted>,
+    on_exited: Option<OnExited>,
+}
+
+/// Watches the running processes by taking a process [`Snapshot`] every poll interval on a
+/// background thread and diffing it against the previous one, calling
+/// [`on_started`](Self::on_started) for every process that appeared and
+/// [`on_exited`](Self::on_exited) for every process that disappeared in between.
+/// The processes already running when the watcher is started aren't reported.
+///
+/// Processes are told apart by their id only. A process that starts and exits between two polls
+/// isn't reported at all, and if an exited process's id is reused by a new process before the
+/// next poll, neither the exit nor the start is reported. The id passed to `on_exited` might thus
+/// already belong to another process, it mustn't be opened expecting the exited one.
+///
+/// The watcher stops and joins its thread when dropped.
+///
+/// # Usage
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let mut watcher = tlhelp32::ProcessWatcher::new(Duration::from_millis(500))
+///     .on_started(|entry| println!("started {}", entry.sz_exe_file.to_string_lossy()))
+///     .on_exited(|pid| println!("exited {}", pid));
+/// watcher.start()?;
+/// ```
+pub struct ProcessWatcher {
+    interval: Duration,
+    callbacks: Arc<Mutex<Callbacks>>,
+    worker: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl ProcessWatcher {
+    /// Creates a stopped watcher that polls every `interval` once started.
+    pub fn new(interval: Duration) -> Self {
+        ProcessWatcher {
+            interval,
+            callbacks: Arc::default(),
+            worker: None,
+        }
+    }
+
+    /// Sets the callback invoked with the entry of every process that appeared since the last
+    /// poll. It is called on the watcher's thread and can be replaced while the watcher runs.
+    pub fn on_started(self, callback: impl FnMut(ProcessEntry) + Send + 'static) -> Self {
+        self.lock().on_started = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback invoked with the id of every process that disappeared since the last
+    /// poll. It is called on the watcher's thread and can be replaced while the watcher runs.
+    pub fn on_exited(self, callback: impl FnMut(u32) + Send + 'static) -> Self {
+        self.lock().on_exited = Some(Box::new(callback));
+        self
+    }
+
+    /// Checks whether the watcher's thread is running.
+    pub fn is_running(&self) -> bool {
+        self.worker.is_some()
+    }
+
+    /// Takes the initial process snapshot and starts polling on a background thread. Does
+    /// nothing if the watcher is already running.
+    /// Polls failing to create a snapshot are skipped, the next poll diffs against the last
+    /// successful one.
+    /// # Errors
+    /// This function fails and returns the appropriate os error if it is unable to create the
+    /// initial [`Snapshot`] or to spawn the thread
+    pub fn start(&mut self) -> Result<()> {
+        if self.worker.is_some() {
+            return Ok(());
+        }
+        let mut known = snapshot_processes()?;
+        let (stop, stopped) = mpsc::channel();
+        let interval = self.interval;
+        let callbacks = Arc::clone(&self.callbacks);
+        let handle = thread::Builder::new()
+            .name("tlhelp32 process watcher".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    let current = match snapshot_processes() {
+                        Ok(current) => current,
+                        Err(_) => continue,
+                    };
+                    let mut callbacks = callbacks.lock().unwrap_or_else(|e| e.into_inner());
+                    if let Some(on_exited) = &mut callbacks.on_exited {
+                        known
+                            .keys()
+                            .filter(|pid| !current.contains_key(pid))
+                            .for_each(|&pid| on_exited(pid));
+                    }
+                    if let Some(on_started) = &mut callbacks.on_started {
+                        current
+                            .values()
+                            .filter(|entry| !known.contains_key(&entry.process_id))
+                            .for_each(|entry| on_started(entry.clone()));
+                    }
+                    known = current;
+                }
+            })?;
+        self.worker = Some((stop, handle));
+        Ok(())
+    }
+
+    /// Stops the watcher and waits for its thread to exit, which happens at the latest after the
+    /// callbacks of the current poll returned. Does nothing if the watcher isn't running.
+    pub fn stop(&mut self) {
+        if let Some((stop, handle)) = self.worker.take() {
+            // the thread has exited already if sending fails
+            let _ = stop.send(());
+            // a panic in a callback has already been reported by the thread
+            let _ = handle.join();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Callbacks> {
+        self.callbacks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for ProcessWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl fmt::Debug for ProcessWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcessWatcher")
+            .field("interval", &self.interval)
+            .field("running", &self.is_running())
+            .finish()
+    }
+}
+
+fn snapshot_processes() -> Result<HashMap<u32, ProcessEntry>> {
+    Ok(Snapshot::new_process()?
+        .map(|entry| (entry.process_id, entry))
+        .collect())
+}